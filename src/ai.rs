@@ -0,0 +1,152 @@
+use bevy::utils::{Duration, Instant};
+
+use crate::game::{GameState, MoveEvent};
+
+// chance branches less likely than this are scored with the heuristic instead of searched
+const MIN_PROBABILITY: f32 = 0.0001;
+
+const EMPTY_WEIGHT: f32 = 2.7;
+const MONOTONICITY_WEIGHT: f32 = 1.0;
+const SMOOTHNESS_WEIGHT: f32 = 0.1;
+const CORNER_WEIGHT: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchLimits {
+    // depth counted in player moves
+    pub depth: u32,
+    // a pass still running when this runs out is dropped for the one before it
+    pub time_budget: Option<Duration>,
+}
+
+// Expected value of every direction, `None` for moves that change nothing.
+pub fn evaluate_moves(game: &GameState, limits: SearchLimits) -> [(MoveEvent, Option<f32>); 4] {
+    let deadline = limits.time_budget.map(|budget| Instant::now() + budget);
+    let mut scores = MoveEvent::ALL.map(|event| (event, None));
+    // depth 1 only looks at the heuristic, so it always finishes
+    for depth in 1..=limits.depth {
+        match evaluate_at_depth(game, depth, deadline) {
+            Some(deeper) => scores = deeper,
+            None => break,
+        }
+    }
+    scores
+}
+
+pub fn best_move(game: &GameState, limits: SearchLimits) -> Option<MoveEvent> {
+    pick_best(&evaluate_moves(game, limits))
+}
+
+pub fn pick_best(scores: &[(MoveEvent, Option<f32>); 4]) -> Option<MoveEvent> {
+    scores
+        .iter()
+        .copied()
+        .filter_map(|(event, score)| Some((event, score?)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(event, _)| event)
+}
+
+// `None` if the deadline passed before the pass was done.
+fn evaluate_at_depth(
+    game: &GameState,
+    depth: u32,
+    deadline: Option<Instant>,
+) -> Option<[(MoveEvent, Option<f32>); 4]> {
+    let mut scores = MoveEvent::ALL.map(|event| (event, None));
+    for (event, score) in &mut scores {
+        let mut next = game.clone();
+        if !next.apply_move(*event).is_noop() {
+            *score = Some(chance_node(&next, depth - 1, 1.0, deadline)?);
+        }
+    }
+    Some(scores)
+}
+
+fn max_node(
+    game: &GameState,
+    depth: u32,
+    probability: f32,
+    deadline: Option<Instant>,
+) -> Option<f32> {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return None;
+    }
+    let mut best = None;
+    for event in MoveEvent::ALL {
+        let mut next = game.clone();
+        if next.apply_move(event).is_noop() {
+            continue;
+        }
+        let score = chance_node(&next, depth, probability, deadline)?;
+        best = Some(best.map_or(score, |best: f32| best.max(score)));
+    }
+    // no move left: the game is lost, which is worse than any board
+    Some(best.unwrap_or(-1000.0))
+}
+
+fn chance_node(
+    game: &GameState,
+    depth: u32,
+    probability: f32,
+    deadline: Option<Instant>,
+) -> Option<f32> {
+    let empties = game.empty_cells();
+    if depth == 0 || empties.is_empty() || probability < MIN_PROBABILITY {
+        return Some(heuristic(game));
+    }
+    let cell_probability = probability / empties.len() as f32;
+    let mut total = 0.0;
+    for pos in &empties {
+        for (value, chance) in [(2, 1.0 - game.four_chance), (4, game.four_chance)] {
+            if chance <= 0.0 {
+                continue;
+            }
+            let mut next = game.clone();
+            next.set(*pos, Some(value));
+            total += chance * max_node(&next, depth - 1, cell_probability * chance, deadline)?;
+        }
+    }
+    Some(total / empties.len() as f32)
+}
+
+pub fn heuristic(game: &GameState) -> f32 {
+    let size = game.size;
+    let rank = |x: i32, y: i32| {
+        game.get((x, y).into())
+            .map_or(0.0, |value| (value as f32).log2())
+    };
+    let empty = game.cells.iter().filter(|cell| cell.is_none()).count() as f32;
+
+    let mut smoothness = 0.0;
+    let mut monotonicity = 0.0;
+    for line in 0..size {
+        // increases and decreases along a row and along a column
+        let mut rows = [0.0, 0.0];
+        let mut columns = [0.0, 0.0];
+        for i in 0..size - 1 {
+            let (a, b) = (rank(i, line), rank(i + 1, line));
+            rows[(a > b) as usize] += (a - b).abs();
+            if a > 0.0 && b > 0.0 {
+                smoothness -= (a - b).abs();
+            }
+            let (a, b) = (rank(line, i), rank(line, i + 1));
+            columns[(a > b) as usize] += (a - b).abs();
+            if a > 0.0 && b > 0.0 {
+                smoothness -= (a - b).abs();
+            }
+        }
+        monotonicity -= rows[0].min(rows[1]) + columns[0].min(columns[1]);
+    }
+
+    let max = (game.max_tile().max(1) as f32).log2();
+    let corners = [(0, 0), (size - 1, 0), (0, size - 1), (size - 1, size - 1)];
+    let corner = if corners.iter().any(|(x, y)| rank(*x, *y) == max) {
+        max
+    } else {
+        0.0
+    };
+
+    EMPTY_WEIGHT * empty
+        + MONOTONICITY_WEIGHT * monotonicity
+        + SMOOTHNESS_WEIGHT * smoothness
+        + CORNER_WEIGHT * corner
+}
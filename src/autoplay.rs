@@ -0,0 +1,277 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+    utils::Duration,
+};
+use bevy_2048::{
+    ai::{self, SearchLimits},
+    game::MoveEvent,
+};
+
+use crate::{
+    buffer::buffer_moves,
+    controls::{Action, Actions},
+    daily::assists_allowed,
+    move_direction,
+    theme::Palette,
+    AppState, BoardPivot, Config, Game, KeepGoingEvent, MoveInputEvent, PieceFont,
+};
+
+pub struct AutoplayPlugin;
+
+impl Plugin for AutoplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Autoplay>()
+            .add_event::<HintEvent>()
+            .init_gizmo_group::<HintGizmos>()
+            .add_systems(Startup, configure_hint_gizmos)
+            .add_systems(Update, autoplay_input)
+            .add_systems(
+                Update,
+                (
+                    clear_hint.run_if(resource_changed::<Game>),
+                    hint_event
+                        .run_if(on_event::<HintEvent>())
+                        .run_if(assists_allowed),
+                )
+                    .chain()
+                    .before(poll_evaluation),
+            )
+            .add_systems(
+                Update,
+                autoplay_move.run_if(
+                    in_state(AppState::Input)
+                        .and_then(autoplay_enabled)
+                        .and_then(assists_allowed),
+                ),
+            )
+            .add_systems(
+                Update,
+                autoplay_keep_going.run_if(
+                    in_state(AppState::Won)
+                        .and_then(autoplay_enabled)
+                        .and_then(assists_allowed),
+                ),
+            )
+            .add_systems(
+                Update,
+                // the chosen move goes through the buffer like the player's, so the two
+                // can't both be played on the same board
+                poll_evaluation
+                    .after(autoplay_move)
+                    .before(buffer_moves)
+                    .run_if(resource_exists::<Evaluation>),
+            )
+            .add_systems(Update, draw_hint.run_if(resource_exists::<Hint>));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Autoplay(pub bool);
+
+#[derive(Event)]
+pub struct HintEvent;
+
+// The search running on the compute pool, for the board it was started on.
+#[derive(Resource)]
+struct Evaluation {
+    task: Task<[(MoveEvent, Option<f32>); 4]>,
+    cells: Vec<Option<i32>>,
+    show_hint: bool,
+    play: bool,
+}
+
+#[derive(Resource)]
+struct Hint {
+    best: Option<MoveEvent>,
+}
+
+#[derive(Component)]
+struct HintLabel;
+
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct HintGizmos;
+
+pub fn search_limits(config: &Config) -> SearchLimits {
+    SearchLimits {
+        depth: config.ai_depth,
+        time_budget: Some(Duration::from_millis(config.ai_time_budget_ms)),
+    }
+}
+
+fn autoplay_enabled(autoplay: Res<Autoplay>) -> bool {
+    autoplay.0
+}
+
+fn configure_hint_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
+    let (config, _) = config_store.config_mut::<HintGizmos>();
+    config.line_width = 10.0;
+}
+
+fn autoplay_input(
+    actions: Actions,
+    mut autoplay: ResMut<Autoplay>,
+    mut hint_event: EventWriter<HintEvent>,
+) {
+    if actions.just_pressed(Action::Autoplay) {
+        autoplay.0 = !autoplay.0;
+    }
+    if actions.just_pressed(Action::Hint) {
+        hint_event.send(HintEvent);
+    }
+}
+
+fn start_evaluation(game: &Game, config: &Config) -> Evaluation {
+    let state = game.0.clone();
+    let limits = search_limits(config);
+    let task = AsyncComputeTaskPool::get().spawn(async move { ai::evaluate_moves(&state, limits) });
+    Evaluation {
+        task,
+        cells: game.cells.clone(),
+        show_hint: false,
+        play: false,
+    }
+}
+
+fn hint_event(
+    mut commands: Commands,
+    mut hint_event: EventReader<HintEvent>,
+    state: Res<State<AppState>>,
+    game: Res<Game>,
+    config: Res<Config>,
+    evaluation: Option<ResMut<Evaluation>>,
+    hint: Option<Res<Hint>>,
+) {
+    hint_event.clear();
+    if *state.get() != AppState::Input || hint.is_some() {
+        return;
+    }
+    match evaluation {
+        Some(mut evaluation) if evaluation.cells == game.cells => evaluation.show_hint = true,
+        _ => {
+            let mut evaluation = start_evaluation(&game, &config);
+            evaluation.show_hint = true;
+            commands.insert_resource(evaluation);
+        }
+    }
+}
+
+fn autoplay_move(
+    mut commands: Commands,
+    game: Res<Game>,
+    config: Res<Config>,
+    evaluation: Option<ResMut<Evaluation>>,
+) {
+    match evaluation {
+        Some(mut evaluation) if evaluation.cells == game.cells => evaluation.play = true,
+        _ => {
+            let mut evaluation = start_evaluation(&game, &config);
+            evaluation.play = true;
+            commands.insert_resource(evaluation);
+        }
+    }
+}
+
+fn poll_evaluation(
+    mut commands: Commands,
+    mut evaluation: ResMut<Evaluation>,
+    game: Res<Game>,
+    state: Res<State<AppState>>,
+    autoplay: Res<Autoplay>,
+    font: Res<PieceFont>,
+    palette: Res<Palette>,
+    config: Res<Config>,
+    pivot: Res<BoardPivot>,
+    mut move_input: EventWriter<MoveInputEvent>,
+) {
+    let Some(scores) = block_on(poll_once(&mut evaluation.task)) else {
+        return;
+    };
+    commands.remove_resource::<Evaluation>();
+    // the board moved on while the search was running
+    if evaluation.cells != game.cells || *state.get() != AppState::Input {
+        return;
+    }
+    let best = ai::pick_best(&scores);
+    if evaluation.show_hint {
+        commands.insert_resource(Hint { best });
+        let center = Vec2::new(config.board_size(), -config.board_size()) / 2.0;
+        let offset = config.board_size() / 2.0 + 40.0;
+        commands.entity(pivot.0).with_children(|parent| {
+            for (event, score) in scores {
+                let label = match score {
+                    Some(score) => format!("{score:.0}"),
+                    None => "-".to_string(),
+                };
+                let color = if Some(event) == best {
+                    palette.accent
+                } else {
+                    palette.text
+                };
+                let pos = center + move_direction(event) * (offset + horizontal_pad(event));
+                parent.spawn((
+                    Text2dBundle {
+                        text: Text::from_section(
+                            label,
+                            TextStyle {
+                                font: font.0.clone_weak(),
+                                font_size: 30.0,
+                                color,
+                            },
+                        ),
+                        transform: Transform::from_translation(pos.extend(5.0)),
+                        ..default()
+                    },
+                    HintLabel,
+                ));
+            }
+        });
+    }
+    if evaluation.play && autoplay.0 {
+        if let Some(event) = best {
+            move_input.send(MoveInputEvent(event));
+        }
+    }
+}
+
+// side labels need room for their width
+fn horizontal_pad(event: MoveEvent) -> f32 {
+    match event {
+        MoveEvent::Right | MoveEvent::Left => 30.0,
+        _ => 0.0,
+    }
+}
+
+fn draw_hint(
+    hint: Res<Hint>,
+    config: Res<Config>,
+    palette: Res<Palette>,
+    pivot: Res<BoardPivot>,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos<HintGizmos>,
+) {
+    let (Some(best), Ok(transform)) = (hint.best, transforms.get(pivot.0)) else {
+        return;
+    };
+    let center = transform.translation().truncate()
+        + Vec2::new(config.board_size(), -config.board_size()) / 2.0;
+    let length = config.board_size() / 3.0;
+    gizmos
+        .arrow_2d(
+            center - move_direction(best) * length / 2.0,
+            center + move_direction(best) * length / 2.0,
+            palette.accent.with_alpha(0.8),
+        )
+        .with_tip_length(length / 3.0);
+}
+
+fn autoplay_keep_going(mut keep_going: EventWriter<KeepGoingEvent>) {
+    keep_going.send(KeepGoingEvent);
+}
+
+fn clear_hint(mut commands: Commands, query: Query<Entity, With<HintLabel>>) {
+    commands.remove_resource::<Hint>();
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
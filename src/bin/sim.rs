@@ -0,0 +1,260 @@
+// Plays many games without a window and prints statistics about how a policy does.
+
+use std::{collections::BTreeMap, process::exit, thread};
+
+use bevy::utils::Duration;
+use bevy_2048::{
+    ai::{self, SearchLimits},
+    game::{GameState, MoveEvent},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+const USAGE: &str = "usage: sim [--games <n>] [--policy <random|greedy|corner|expectimax>] \
+[--size <3-8>] [--seed <n>] [--target <value>] [--spawn-four-chance <0-1>] [--depth <n>] \
+[--time-budget <ms>] [--threads <n>]";
+
+#[derive(Clone, Copy, Debug)]
+enum Policy {
+    Random,
+    Greedy,
+    Corner,
+    Expectimax,
+}
+
+#[derive(Clone, Debug)]
+struct Options {
+    games: u32,
+    policy: Policy,
+    size: i32,
+    seed: u64,
+    target: i32,
+    spawn_four_chance: f32,
+    depth: u32,
+    time_budget_ms: Option<u64>,
+    threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            games: 1000,
+            policy: Policy::Greedy,
+            size: 4,
+            seed: 0,
+            target: 2048,
+            spawn_four_chance: 0.1,
+            depth: 2,
+            time_budget_ms: None,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+struct GameResult {
+    score: i32,
+    max_tile: i32,
+    moves: u32,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            exit(2);
+        }
+    };
+    let results = run(&options);
+    report(&options, &results);
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(USAGE.to_string())?;
+        match flag.as_str() {
+            "--games" => options.games = parse(flag, value)?,
+            "--policy" => {
+                options.policy = match value.as_str() {
+                    "random" => Policy::Random,
+                    "greedy" => Policy::Greedy,
+                    "corner" => Policy::Corner,
+                    "expectimax" => Policy::Expectimax,
+                    _ => return Err(format!("unknown policy: {value}")),
+                }
+            }
+            "--size" => options.size = parse(flag, value)?,
+            "--seed" => options.seed = parse(flag, value)?,
+            "--target" => options.target = parse(flag, value)?,
+            "--spawn-four-chance" => options.spawn_four_chance = parse(flag, value)?,
+            "--depth" => options.depth = parse(flag, value)?,
+            "--time-budget" => options.time_budget_ms = Some(parse(flag, value)?),
+            "--threads" => options.threads = parse(flag, value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if !(3..=8).contains(&options.size) {
+        return Err(format!(
+            "board size must be between 3 and 8, got {}",
+            options.size
+        ));
+    }
+    if !(0.0..=1.0).contains(&options.spawn_four_chance) {
+        return Err("spawn four chance must be between 0 and 1".to_string());
+    }
+    if options.depth == 0 || options.threads == 0 {
+        return Err("depth and threads must be at least 1".to_string());
+    }
+    Ok(options)
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+// Game `i` always uses seed `seed + i`, so results don't depend on the thread count.
+fn run(options: &Options) -> Vec<GameResult> {
+    let threads = options.threads.min(options.games.max(1) as usize);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                scope.spawn(move || {
+                    (thread as u32..options.games)
+                        .step_by(threads)
+                        .map(|i| play_game(options, options.seed.wrapping_add(i as u64)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn play_game(options: &Options, seed: u64) -> GameResult {
+    let mut game = GameState::new(options.size, seed);
+    game.four_chance = options.spawn_four_chance;
+    game.spawn();
+    game.spawn();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let limits = SearchLimits {
+        depth: options.depth,
+        time_budget: options.time_budget_ms.map(Duration::from_millis),
+    };
+    let mut moves = 0;
+    while let Some(event) = choose_move(options.policy, &game, limits, &mut rng) {
+        game.play(event);
+        moves += 1;
+    }
+    GameResult {
+        score: game.score,
+        max_tile: game.max_tile(),
+        moves,
+    }
+}
+
+// `None` once no move changes the board.
+fn choose_move(
+    policy: Policy,
+    game: &GameState,
+    limits: SearchLimits,
+    rng: &mut StdRng,
+) -> Option<MoveEvent> {
+    let legal: Vec<(MoveEvent, i32)> = MoveEvent::ALL
+        .into_iter()
+        .filter_map(|event| {
+            let outcome = game.clone().apply_move(event);
+            (!outcome.is_noop()).then_some((event, outcome.score))
+        })
+        .collect();
+    match policy {
+        Policy::Random => legal.choose(rng).map(|(event, _)| *event),
+        // first of the best, so ties keep the order of `MoveEvent::ALL`
+        Policy::Greedy => legal
+            .iter()
+            .rev()
+            .max_by_key(|(_, score)| *score)
+            .map(|(event, _)| *event),
+        // keeps the big tiles in the bottom left corner
+        Policy::Corner => [
+            MoveEvent::Down,
+            MoveEvent::Left,
+            MoveEvent::Right,
+            MoveEvent::Up,
+        ]
+        .into_iter()
+        .find(|event| legal.iter().any(|(legal, _)| legal == event)),
+        Policy::Expectimax => ai::best_move(game, limits),
+    }
+}
+
+fn report(options: &Options, results: &[GameResult]) {
+    if results.is_empty() {
+        println!("no games played");
+        return;
+    }
+    let count = results.len();
+    println!(
+        "{count} games, policy {:?}, size {}, seeds {}..{}",
+        options.policy,
+        options.size,
+        options.seed,
+        options.seed.wrapping_add(count as u64)
+    );
+
+    let wins = results
+        .iter()
+        .filter(|result| result.max_tile >= options.target)
+        .count();
+    println!(
+        "win rate (reached {}): {:.1}% ({wins}/{count})",
+        options.target,
+        100.0 * wins as f32 / count as f32
+    );
+
+    let mut scores: Vec<i32> = results.iter().map(|result| result.score).collect();
+    scores.sort_unstable();
+    println!("\nscore");
+    print_distribution(&scores);
+
+    let mut moves: Vec<i32> = results.iter().map(|result| result.moves as i32).collect();
+    moves.sort_unstable();
+    println!("\nmoves per game");
+    print_distribution(&moves);
+
+    let mut tiles: BTreeMap<i32, usize> = BTreeMap::new();
+    for result in results {
+        *tiles.entry(result.max_tile).or_default() += 1;
+    }
+    let widest = tiles.values().copied().max().unwrap_or(1);
+    println!("\nmax tile");
+    for (tile, games) in tiles {
+        println!(
+            "{tile:>8} {games:>7} {:>5.1}% {}",
+            100.0 * games as f32 / count as f32,
+            "#".repeat((games * 40).div_ceil(widest))
+        );
+    }
+}
+
+// `values` must be sorted.
+fn print_distribution(values: &[i32]) {
+    let percentile = |p: usize| values[(values.len() - 1) * p / 100];
+    let mean = values.iter().map(|value| *value as f64).sum::<f64>() / values.len() as f64;
+    println!(
+        "  mean {mean:.1}  min {}  p10 {}  p25 {}  median {}  p75 {}  p90 {}  max {}",
+        percentile(0),
+        percentile(10),
+        percentile(25),
+        percentile(50),
+        percentile(75),
+        percentile(90),
+        percentile(100)
+    );
+}
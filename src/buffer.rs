@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_2048::game::MoveEvent;
+use bevy_tweening::Animator;
+
+use crate::{
+    set_board, AppState, Config, Game, InvalidMoveEvent, MoveInputEvent, MoveType, NewGameEvent,
+    RestoreBoardEvent,
+};
+
+// tween speed while fast-forwarding, enough to finish any slide in a frame
+const FAST_FORWARD_SPEED: f32 = 100.0;
+
+pub struct BufferPlugin;
+
+impl Plugin for BufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveQueue>()
+            .add_systems(Update, buffer_moves.before(set_board))
+            .add_systems(
+                Update,
+                clear_queue
+                    .run_if(on_event::<NewGameEvent>().or_else(on_event::<RestoreBoardEvent>())),
+            )
+            .add_systems(OnEnter(AppState::Won), clear_queue)
+            .add_systems(OnEnter(AppState::GameOver), clear_queue)
+            .add_systems(OnEnter(AppState::Replay), clear_queue);
+    }
+}
+
+// Moves pressed while the board was still animating, played in order once it settles.
+#[derive(Resource, Default)]
+pub struct MoveQueue(VecDeque<MoveEvent>);
+
+// Moves that would change nothing are rejected here, before anything records them.
+pub fn buffer_moves(
+    mut move_input: EventReader<MoveInputEvent>,
+    mut move_event: EventWriter<MoveEvent>,
+    mut invalid_event: EventWriter<InvalidMoveEvent>,
+    mut queue: ResMut<MoveQueue>,
+    mut animators: Query<&mut Animator<Transform>, With<MoveType>>,
+    state: Res<State<AppState>>,
+    config: Res<Config>,
+    game: Res<Game>,
+) {
+    // one move per frame, older ones first
+    let mut sent = false;
+    let mut play = |event: MoveEvent| {
+        if game.can_play(event) {
+            move_event.send(event);
+            true
+        } else {
+            invalid_event.send(InvalidMoveEvent(event));
+            false
+        }
+    };
+    if *state.get() == AppState::Input {
+        while let Some(event) = queue.0.pop_front() {
+            if play(event) {
+                sent = true;
+                break;
+            }
+        }
+    }
+    for MoveInputEvent(event) in move_input.read() {
+        match state.get() {
+            AppState::Input if !sent => {
+                sent = play(*event);
+            }
+            AppState::Input | AppState::Anim | AppState::PostAnim => {
+                if queue.0.len() < config.input_queue_len {
+                    queue.0.push_back(*event);
+                }
+                if config.fast_forward {
+                    for mut animator in animators.iter_mut() {
+                        animator.set_speed(FAST_FORWARD_SPEED);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn clear_queue(mut queue: ResMut<MoveQueue>) {
+    queue.0.clear();
+}
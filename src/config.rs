@@ -0,0 +1,421 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_tweening::{EaseFunction, EaseMethod};
+use serde::{Deserialize, Serialize};
+
+use crate::save;
+
+// size of the "back" and "piece" slices in sprites.aseprite
+const SPRITE_SIZE: f32 = 150.0;
+// room kept free above and below the board for the title and the score
+const MARGIN: f32 = 150.0;
+const DEFAULT_PATH: &str = "config.ron";
+// what the settings screen changes, saved with the rest of the player data
+const SETTINGS_FILE: &str = "settings.ron";
+
+const USAGE: &str = "usage: bevy-2048 [--config <file.ron>] [--size <3-8>] [--tile-size <px>] \
+[--pad <px>] [--spawn-four-chance <0-1>] [--target <value>] [--undo-limit <n>] [--seed <n>] \
+[--replay <file>] [--ai-depth <n>] [--ai-time-budget <ms>] [--swipe-dead-zone <px>] \
+[--swipe-min-velocity <px/s>] [--swipe-debug <true|false>] [--stick-deadzone <0-1>] \
+[--stick-flick <0-1>] [--input-queue <n>] [--fast-forward <true|false>] \
+[--animation-speed <slow|normal|fast|instant>] [--theme <name>] [--exponent-labels <value>] \
+[--shake-invalid-moves <true|false>] [--timed-secs <s>] [--time-bonus-from <value>]";
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub size: i32,
+    pub tile_size: i32,
+    // tile size asked for, before shrinking it to fit the window
+    #[serde(skip)]
+    pub max_tile_size: i32,
+    pub pad: i32,
+    pub window_size: Vec2,
+    pub target: i32,
+    pub spawn_four_chance: f32,
+    pub history_len: usize,
+    pub undo_limit: Option<u32>,
+    // every new game starts from this seed instead of a random one
+    pub seed: Option<u64>,
+    pub ai_depth: u32,
+    pub ai_time_budget_ms: u64,
+    // swipes shorter or slower than this are ignored
+    pub swipe_dead_zone: f32,
+    pub swipe_min_velocity: f32,
+    pub swipe_debug: bool,
+    // a left stick flick moves once it passes `stick_flick`, and again only after
+    // coming back inside `stick_deadzone`
+    pub stick_deadzone: f32,
+    pub stick_flick: f32,
+    // moves pressed during an animation are kept until it ends, up to this many
+    pub input_queue_len: usize,
+    // a move pressed during an animation also finishes it at once
+    pub fast_forward: bool,
+    pub animation_speed: AnimationSpeed,
+    // slide duration is per cell travelled
+    pub slide_animation: Animation,
+    pub spawn_animation: Animation,
+    pub merge_animation: Animation,
+    // assets/themes/<theme>.theme.ron
+    pub theme: String,
+    // tiles from this value on are labelled as powers of two
+    pub exponent_labels: Option<i32>,
+    // 0 to 1, effects and music are both scaled by the master volume
+    pub master_volume: f32,
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+    // the board shakes when a move would change nothing
+    pub shake_invalid_moves: bool,
+    // length of a timed game, and the smallest merge that gives time back
+    pub timed_secs: u64,
+    pub time_bonus_from: i32,
+    // replay file given on the command line, played instead of a new game
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            size: 4,
+            tile_size: 150,
+            max_tile_size: 150,
+            pad: 0,
+            window_size: (900.0, 900.0).into(),
+            target: 2048,
+            spawn_four_chance: 0.1,
+            history_len: 100,
+            undo_limit: None,
+            seed: None,
+            ai_depth: 3,
+            ai_time_budget_ms: 100,
+            swipe_dead_zone: 40.0,
+            swipe_min_velocity: 200.0,
+            swipe_debug: false,
+            stick_deadzone: 0.3,
+            stick_flick: 0.7,
+            input_queue_len: 2,
+            fast_forward: false,
+            animation_speed: AnimationSpeed::Normal,
+            slide_animation: Animation {
+                ease: Ease::QuadraticOut,
+                duration_ms: 40,
+            },
+            spawn_animation: Animation {
+                ease: Ease::BackOut,
+                duration_ms: 150,
+            },
+            merge_animation: Animation {
+                ease: Ease::QuadraticOut,
+                duration_ms: 120,
+            },
+            theme: "classic".to_string(),
+            exponent_labels: None,
+            master_volume: 0.8,
+            sfx_volume: 1.0,
+            music_volume: 0.0,
+            shake_invalid_moves: true,
+            timed_secs: 180,
+            time_bonus_from: 128,
+            replay: None,
+        }
+    }
+}
+
+impl Config {
+    // Defaults, overridden by the config file, then by the saved settings, then by the
+    // command line.
+    pub fn load() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let path = args
+            .iter()
+            .position(|arg| arg == "--config")
+            .map(|i| args.get(i + 1).map(PathBuf::from).ok_or(USAGE.to_string()))
+            .transpose()?;
+        let mut config = match &path {
+            Some(path) => Self::from_file(path)?,
+            // only a missing default file means defaults, a broken one is still an error
+            None => {
+                let path = PathBuf::from(DEFAULT_PATH);
+                if path.exists() {
+                    Self::from_file(&path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+        if let Some(settings) =
+            save::read(SETTINGS_FILE).and_then(|text| ron::from_str::<Settings>(&text).ok())
+        {
+            // a hand-edited settings.ron can't be fixed from inside the game, skip it instead
+            let mut saved = config.clone();
+            saved.apply_settings(&settings);
+            match saved.validate() {
+                Ok(()) => config = saved,
+                Err(err) => eprintln!("ignoring {SETTINGS_FILE}: {err}"),
+            }
+        }
+        config.apply_args(&args)?;
+        config.validate()?;
+        config.max_tile_size = config.tile_size;
+        config.fit_board();
+        Ok(config)
+    }
+
+    pub fn settings(&self) -> Settings {
+        Settings {
+            size: self.size,
+            animation_speed: self.animation_speed,
+            spawn_four_chance: self.spawn_four_chance,
+            theme: self.theme.clone(),
+            master_volume: self.master_volume,
+            sfx_volume: self.sfx_volume,
+            music_volume: self.music_volume,
+        }
+    }
+
+    // The tile size is left alone, `fit_board` again after changing the size.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.size = settings.size;
+        self.animation_speed = settings.animation_speed;
+        self.spawn_four_chance = settings.spawn_four_chance;
+        self.theme = settings.theme.clone();
+        self.master_volume = settings.master_volume;
+        self.sfx_volume = settings.sfx_volume;
+        self.music_volume = settings.music_volume;
+    }
+
+    pub fn save_settings(&self) {
+        match ron::to_string(&self.settings()) {
+            Ok(text) => save::write(SETTINGS_FILE, &text),
+            Err(err) => warn!("could not serialize settings: {err}"),
+        }
+    }
+
+    fn from_file(path: &PathBuf) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {err}", path.display()))?;
+        ron::from_str(&text).map_err(|err| format!("invalid config {}: {err}", path.display()))
+    }
+
+    fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(USAGE.to_string())?;
+            match flag.as_str() {
+                "--config" => {}
+                "--size" => self.size = parse(flag, value)?,
+                "--tile-size" => self.tile_size = parse(flag, value)?,
+                "--pad" => self.pad = parse(flag, value)?,
+                "--spawn-four-chance" => self.spawn_four_chance = parse(flag, value)?,
+                "--target" => self.target = parse(flag, value)?,
+                "--undo-limit" => self.undo_limit = Some(parse(flag, value)?),
+                "--seed" => self.seed = Some(parse(flag, value)?),
+                "--replay" => self.replay = Some(PathBuf::from(value)),
+                "--ai-depth" => self.ai_depth = parse(flag, value)?,
+                "--ai-time-budget" => self.ai_time_budget_ms = parse(flag, value)?,
+                "--swipe-dead-zone" => self.swipe_dead_zone = parse(flag, value)?,
+                "--swipe-min-velocity" => self.swipe_min_velocity = parse(flag, value)?,
+                "--swipe-debug" => self.swipe_debug = parse(flag, value)?,
+                "--stick-deadzone" => self.stick_deadzone = parse(flag, value)?,
+                "--stick-flick" => self.stick_flick = parse(flag, value)?,
+                "--input-queue" => self.input_queue_len = parse(flag, value)?,
+                "--fast-forward" => self.fast_forward = parse(flag, value)?,
+                "--animation-speed" => self.animation_speed = parse(flag, value)?,
+                "--theme" => self.theme = value.clone(),
+                "--exponent-labels" => self.exponent_labels = Some(parse(flag, value)?),
+                "--shake-invalid-moves" => self.shake_invalid_moves = parse(flag, value)?,
+                "--timed-secs" => self.timed_secs = parse(flag, value)?,
+                "--time-bonus-from" => self.time_bonus_from = parse(flag, value)?,
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(3..=8).contains(&self.size) {
+            return Err(format!(
+                "board size must be between 3 and 8, got {}",
+                self.size
+            ));
+        }
+        if self.tile_size <= 0 || self.pad < 0 {
+            return Err("tile size must be positive and padding not negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.spawn_four_chance) {
+            return Err("spawn four chance must be between 0 and 1".to_string());
+        }
+        if [self.master_volume, self.sfx_volume, self.music_volume]
+            .iter()
+            .any(|volume| !(0.0..=1.0).contains(volume))
+        {
+            return Err("volumes must be between 0 and 1".to_string());
+        }
+        if self.swipe_dead_zone < 0.0 || self.swipe_min_velocity < 0.0 {
+            return Err("swipe dead zone and minimum velocity must not be negative".to_string());
+        }
+        if !(0.0 <= self.stick_deadzone
+            && self.stick_deadzone < self.stick_flick
+            && self.stick_flick <= 1.0)
+        {
+            return Err(
+                "stick dead zone must be below stick flick, both between 0 and 1".to_string(),
+            );
+        }
+        if self.timed_secs == 0 {
+            return Err("timed games must last at least a second".to_string());
+        }
+        if self.ai_depth == 0 {
+            return Err("ai depth must be at least 1".to_string());
+        }
+        if self.target < 4 || self.target.count_ones() != 1 {
+            return Err(format!(
+                "target must be a power of two, got {}",
+                self.target
+            ));
+        }
+        if self.time_bonus_from < 4 || self.time_bonus_from.count_ones() != 1 {
+            return Err(format!(
+                "time bonus tile must be a power of two, got {}",
+                self.time_bonus_from
+            ));
+        }
+        Ok(())
+    }
+
+    // Shrinks the tiles until the whole board fits between the title and the score.
+    pub fn fit_board(&mut self) {
+        let available = self.window_size.min_element() - 2.0 * MARGIN;
+        let max_tile = (available / self.size as f32) as i32 - 2 * self.pad;
+        self.tile_size = self.max_tile_size.min(max_tile).max(1);
+    }
+
+    pub fn board_size(&self) -> f32 {
+        (self.size * (self.tile_size + 2 * self.pad)) as f32
+    }
+
+    pub fn tile_scale(&self) -> Vec3 {
+        Vec3::splat(self.tile_size as f32 / SPRITE_SIZE)
+    }
+}
+
+// Fields missing from an older settings file keep their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub size: i32,
+    pub animation_speed: AnimationSpeed,
+    pub spawn_four_chance: f32,
+    pub theme: String,
+    pub master_volume: f32,
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Config::default().settings()
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimationSpeed {
+    Slow,
+    Normal,
+    Fast,
+    Instant,
+}
+
+impl AnimationSpeed {
+    pub const ALL: [AnimationSpeed; 4] = [
+        AnimationSpeed::Slow,
+        AnimationSpeed::Normal,
+        AnimationSpeed::Fast,
+        AnimationSpeed::Instant,
+    ];
+
+    // `None` when tweens are skipped altogether
+    pub fn multiplier(self) -> Option<f32> {
+        match self {
+            AnimationSpeed::Slow => Some(0.5),
+            AnimationSpeed::Normal => Some(1.0),
+            AnimationSpeed::Fast => Some(2.0),
+            AnimationSpeed::Instant => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|speed| *speed == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl std::str::FromStr for AnimationSpeed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|speed| format!("{speed:?}").eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Animation {
+    pub ease: Ease,
+    pub duration_ms: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Ease {
+    Linear,
+    QuadraticIn,
+    QuadraticOut,
+    QuadraticInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticOut,
+    BounceOut,
+}
+
+impl Ease {
+    pub fn method(self) -> EaseMethod {
+        let function = match self {
+            Ease::Linear => return EaseMethod::Linear,
+            Ease::QuadraticIn => EaseFunction::QuadraticIn,
+            Ease::QuadraticOut => EaseFunction::QuadraticOut,
+            Ease::QuadraticInOut => EaseFunction::QuadraticInOut,
+            Ease::CubicIn => EaseFunction::CubicIn,
+            Ease::CubicOut => EaseFunction::CubicOut,
+            Ease::CubicInOut => EaseFunction::CubicInOut,
+            Ease::SineIn => EaseFunction::SineIn,
+            Ease::SineOut => EaseFunction::SineOut,
+            Ease::SineInOut => EaseFunction::SineInOut,
+            Ease::BackIn => EaseFunction::BackIn,
+            Ease::BackOut => EaseFunction::BackOut,
+            Ease::BackInOut => EaseFunction::BackInOut,
+            Ease::ElasticOut => EaseFunction::ElasticOut,
+            Ease::BounceOut => EaseFunction::BounceOut,
+        };
+        EaseMethod::EaseFunction(function)
+    }
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
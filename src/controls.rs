@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_2048::game::MoveEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::{save, theme::Palette, Config, MoveInputEvent, PieceFont};
+
+const CONTROLS_FILE: &str = "controls.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Controls::load())
+            .add_systems(Update, gamepad_stick)
+            .add_systems(
+                Update,
+                (
+                    start_rebinding.run_if(not(resource_exists::<Rebinding>)),
+                    rebind_input.run_if(resource_exists::<Rebinding>),
+                    rebind_ui,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    NewGame,
+    KeepGoing,
+    Undo,
+    Redo,
+    Replay,
+    Hint,
+    Autoplay,
+    AnimationSpeed,
+    Settings,
+    Menu,
+    Pause,
+    Mute,
+}
+
+impl Action {
+    const ALL: [Action; 16] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::NewGame,
+        Action::KeepGoing,
+        Action::Undo,
+        Action::Redo,
+        Action::Replay,
+        Action::Hint,
+        Action::Autoplay,
+        Action::AnimationSpeed,
+        Action::Settings,
+        Action::Menu,
+        Action::Pause,
+        Action::Mute,
+    ];
+
+    fn default_bindings(self) -> Vec<Binding> {
+        use Binding::*;
+        use GamepadButtonType as Pad;
+        match self {
+            Action::Up => vec![
+                Key(KeyCode::KeyW),
+                Key(KeyCode::ArrowUp),
+                Button(Pad::DPadUp),
+            ],
+            Action::Down => vec![
+                Key(KeyCode::KeyS),
+                Key(KeyCode::ArrowDown),
+                Button(Pad::DPadDown),
+            ],
+            Action::Left => vec![
+                Key(KeyCode::KeyA),
+                Key(KeyCode::ArrowLeft),
+                Button(Pad::DPadLeft),
+            ],
+            Action::Right => vec![
+                Key(KeyCode::KeyD),
+                Key(KeyCode::ArrowRight),
+                Button(Pad::DPadRight),
+            ],
+            Action::NewGame => vec![Key(KeyCode::KeyR), Button(Pad::North)],
+            Action::KeepGoing => vec![Key(KeyCode::KeyK), Key(KeyCode::Enter), Button(Pad::South)],
+            Action::Undo => vec![
+                Key(KeyCode::KeyU),
+                CtrlKey(KeyCode::KeyZ),
+                Button(Pad::East),
+            ],
+            Action::Redo => vec![Key(KeyCode::KeyY), Button(Pad::West)],
+            Action::Replay => vec![Key(KeyCode::F9)],
+            Action::Hint => vec![Key(KeyCode::KeyH), Button(Pad::RightTrigger)],
+            Action::Autoplay => vec![Key(KeyCode::KeyI), Button(Pad::LeftTrigger)],
+            Action::AnimationSpeed => vec![Key(KeyCode::KeyF)],
+            Action::Settings => vec![Key(KeyCode::KeyO), Button(Pad::Select)],
+            Action::Menu => vec![Key(KeyCode::KeyM), Button(Pad::Mode)],
+            Action::Pause => vec![Key(KeyCode::Escape), Key(KeyCode::KeyP), Button(Pad::Start)],
+            Action::Mute => vec![Key(KeyCode::KeyV)],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    CtrlKey(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{}", key_name(*key)),
+            Binding::CtrlKey(key) => write!(f, "Ctrl+{}", key_name(*key)),
+            Binding::Button(button) => write!(f, "Pad {button:?}"),
+        }
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key")
+        .or(name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+pub struct Controls {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_bindings()))
+                .collect(),
+        }
+    }
+}
+
+impl Controls {
+    // Actions missing from the saved file keep their defaults.
+    fn load() -> Self {
+        let mut controls = save::read(CONTROLS_FILE)
+            .and_then(|text| ron::from_str::<Controls>(&text).ok())
+            .unwrap_or_default();
+        for action in Action::ALL {
+            controls
+                .bindings
+                .entry(action)
+                .or_insert_with(|| action.default_bindings());
+        }
+        controls
+    }
+
+    fn save(&self) {
+        match ron::to_string(self) {
+            Ok(text) => save::write(CONTROLS_FILE, &text),
+            Err(err) => warn!("could not serialize controls: {err}"),
+        }
+    }
+
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    // A binding belongs to one action at a time.
+    fn bind(&mut self, action: Action, binding: Binding, replace: bool) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|other| *other != binding);
+        }
+        let bindings = self.bindings.entry(action).or_default();
+        if replace {
+            bindings.clear();
+        }
+        bindings.push(binding);
+    }
+}
+
+// Keyboard and gamepad buttons read through the bindings. Nothing fires while rebinding.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    controls: Res<'w, Controls>,
+    rebinding: Option<Res<'w, Rebinding>>,
+}
+
+impl Actions<'_> {
+    pub fn just_pressed(&self, action: Action) -> bool {
+        if self.rebinding.is_some() {
+            return false;
+        }
+        let ctrl = self
+            .keys
+            .any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        self.controls
+            .bindings(action)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => self.keys.just_pressed(*key),
+                Binding::CtrlKey(key) => ctrl && self.keys.just_pressed(*key),
+                Binding::Button(button) => self
+                    .buttons
+                    .get_just_pressed()
+                    .any(|pressed| pressed.button_type == *button),
+            })
+    }
+}
+
+// Menus confirm and go back with fixed keys, so a bad binding can always be undone.
+#[derive(SystemParam)]
+pub struct MenuInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    rebinding: Option<Res<'w, Rebinding>>,
+}
+
+impl MenuInput<'_> {
+    pub fn confirm(&self) -> bool {
+        self.pressed(
+            &[KeyCode::Enter, KeyCode::Space],
+            &[GamepadButtonType::South],
+        )
+    }
+
+    pub fn back(&self) -> bool {
+        self.pressed(
+            &[KeyCode::Escape],
+            &[GamepadButtonType::East, GamepadButtonType::Start],
+        )
+    }
+
+    fn pressed(&self, keys: &[KeyCode], buttons: &[GamepadButtonType]) -> bool {
+        self.rebinding.is_none()
+            && (self.keys.any_just_pressed(keys.iter().copied())
+                || self
+                    .buttons
+                    .get_just_pressed()
+                    .any(|pressed| buttons.contains(&pressed.button_type)))
+    }
+}
+
+// A move per flick: the stick has to go past `stick_flick` and come back inside
+// `stick_deadzone` before it can move again.
+fn gamepad_stick(
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    config: Res<Config>,
+    rebinding: Option<Res<Rebinding>>,
+    mut flicked: Local<HashMap<Gamepad, bool>>,
+    mut move_input: EventWriter<MoveInputEvent>,
+) {
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        let flicked = flicked.entry(gamepad).or_default();
+        if stick.length() < config.stick_deadzone {
+            *flicked = false;
+            continue;
+        }
+        if *flicked || stick.length() < config.stick_flick {
+            continue;
+        }
+        *flicked = true;
+        if rebinding.is_some() {
+            continue;
+        }
+        move_input.send(MoveInputEvent(if stick.x.abs() > stick.y.abs() {
+            if stick.x > 0.0 {
+                MoveEvent::Right
+            } else {
+                MoveEvent::Left
+            }
+        } else if stick.y > 0.0 {
+            MoveEvent::Up
+        } else {
+            MoveEvent::Down
+        }));
+    }
+}
+
+// Walks through every action; the first key or button pressed replaces its bindings,
+// the next ones are added.
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    index: usize,
+    replaced: bool,
+}
+
+#[derive(Component)]
+struct RebindUi;
+
+#[derive(Component)]
+struct RebindLabel;
+
+fn start_rebinding(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_pressed(KeyCode::F2) {
+        commands.insert_resource(Rebinding::default());
+    }
+}
+
+pub fn rebind_input(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut controls: ResMut<Controls>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    // the press that started rebinding is not a binding
+    if rebinding.is_added() {
+        return;
+    }
+    let action = Action::ALL[rebinding.index];
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let mut pressed: Vec<Binding> = vec![];
+    for key in keys.get_just_pressed() {
+        match key {
+            KeyCode::Escape => {
+                controls.save();
+                commands.remove_resource::<Rebinding>();
+                return;
+            }
+            KeyCode::Enter => {
+                rebinding.index += 1;
+                rebinding.replaced = false;
+                if rebinding.index == Action::ALL.len() {
+                    controls.save();
+                    commands.remove_resource::<Rebinding>();
+                }
+                return;
+            }
+            KeyCode::Backspace => {
+                controls.bindings.insert(action, action.default_bindings());
+                rebinding.replaced = false;
+                return;
+            }
+            KeyCode::F2 | KeyCode::ControlLeft | KeyCode::ControlRight => {}
+            key if ctrl => pressed.push(Binding::CtrlKey(*key)),
+            key => pressed.push(Binding::Key(*key)),
+        }
+    }
+    pressed.extend(
+        buttons
+            .get_just_pressed()
+            .map(|button| Binding::Button(button.button_type)),
+    );
+    for binding in pressed {
+        controls.bind(action, binding, !rebinding.replaced);
+        rebinding.replaced = true;
+    }
+}
+
+fn rebind_ui(
+    mut commands: Commands,
+    rebinding: Option<Res<Rebinding>>,
+    controls: Res<Controls>,
+    font: Res<PieceFont>,
+    palette: Res<Palette>,
+    ui: Query<Entity, With<RebindUi>>,
+    mut labels: Query<&mut Text, With<RebindLabel>>,
+) {
+    let Some(rebinding) = rebinding else {
+        for entity in ui.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+    let action = Action::ALL[rebinding.index];
+    let bindings: Vec<String> = controls
+        .bindings(action)
+        .iter()
+        .map(|binding| binding.to_string())
+        .collect();
+    let label = format!("{action:?}: {}", bindings.join(", "));
+    if let Ok(mut text) = labels.get_single_mut() {
+        text.sections[0].value = label;
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(30.0),
+                    ..default()
+                },
+                background_color: palette.background.with_alpha(0.9).into(),
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+            RebindUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font: font.0.clone_weak(),
+                        font_size: 50.0,
+                        color: palette.accent,
+                    },
+                ),
+                RebindLabel,
+            ));
+            parent.spawn(TextBundle::from_section(
+                "press keys or buttons to bind\nEnter: next  Backspace: default  Esc: done",
+                TextStyle {
+                    font: font.0.clone_weak(),
+                    font_size: 30.0,
+                    color: palette.text,
+                },
+            ));
+        });
+}
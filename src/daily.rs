@@ -0,0 +1,420 @@
+use std::fmt;
+
+use bevy::{prelude::*, utils::SystemTime};
+use bevy_2048::game::GameState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    add_piece_event, new_game_event, replay::GameRecord, replay::ReplayPlayer, save,
+    theme::Palette, AddPieceEvent, Config, Game, GameMode, NewGameEvent, PieceFont,
+};
+
+const HISTORY_FILE: &str = "daily.ron";
+// everyone plays the same rules, whatever their settings
+pub const SIZE: i32 = 4;
+const FOUR_CHANCE: f32 = 0.1;
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DailyHistory::load())
+            .add_systems(
+                Update,
+                start_daily
+                    .after(new_game_event)
+                    .run_if(on_event::<NewGameEvent>()),
+            )
+            .add_systems(
+                Update,
+                // a new game resets the board before `start_daily` drops the DailyGame, so
+                // that frame would record the fresh board over the result
+                record_daily
+                    .after(add_piece_event)
+                    .run_if(on_event::<AddPieceEvent>())
+                    .run_if(not(on_event::<NewGameEvent>()))
+                    .run_if(resource_exists::<DailyGame>)
+                    .run_if(not(resource_exists::<ReplayPlayer>)),
+            );
+    }
+}
+
+// Days since 1970-01-01, in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Day(pub i64);
+
+impl Day {
+    pub fn today() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        Day((secs / 86_400) as i64)
+    }
+
+    // (year, month, day of the month), months and days counted from 1.
+    pub fn date(self) -> (i64, u32, u32) {
+        // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = self.0 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        (year, month, day)
+    }
+
+    // 0 for Monday.
+    pub fn weekday(self) -> i64 {
+        (self.0 + 3).rem_euclid(7)
+    }
+
+    // The date as a number, 20240131, so the seed shown in game tells the day.
+    pub fn seed(self) -> u64 {
+        let (year, month, day) = self.date();
+        year as u64 * 10_000 + month as u64 * 100 + day as u64
+    }
+}
+
+impl fmt::Display for Day {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.date();
+        write!(f, "{year}-{month:02}-{day:02}")
+    }
+}
+
+pub fn new_game(day: Day) -> GameState {
+    GameState {
+        four_chance: FOUR_CHANCE,
+        ..GameState::new(SIZE, day.seed())
+    }
+}
+
+// The daily board being played. Only the first game of a day counts, the others are
+// practice.
+#[derive(Resource, Clone, Copy, Serialize, Deserialize)]
+pub struct DailyGame {
+    pub day: Day,
+    pub practice: bool,
+}
+
+// Undo, hints and autoplay are off for the attempt that counts.
+pub fn assists_allowed(daily: Option<Res<DailyGame>>) -> bool {
+    daily.is_none_or(|daily| daily.practice)
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub day: Day,
+    pub score: i32,
+    pub max_tile: i32,
+    pub moves: usize,
+}
+
+// One result per day played, oldest first.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct DailyHistory {
+    results: Vec<DailyResult>,
+}
+
+impl DailyHistory {
+    fn load() -> Self {
+        save::read(HISTORY_FILE)
+            .and_then(|text| ron::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match ron::to_string(self) {
+            Ok(text) => save::write(HISTORY_FILE, &text),
+            Err(err) => warn!("could not serialize daily history: {err}"),
+        }
+    }
+
+    pub fn get(&self, day: Day) -> Option<&DailyResult> {
+        self.results.iter().find(|result| result.day == day)
+    }
+
+    // Days in a row up to today, or up to yesterday while today is still to play.
+    pub fn streak(&self, today: Day) -> usize {
+        let mut day = if self.get(today).is_some() {
+            today
+        } else {
+            Day(today.0 - 1)
+        };
+        let mut streak = 0;
+        while self.get(day).is_some() {
+            streak += 1;
+            day.0 -= 1;
+        }
+        streak
+    }
+
+    pub fn best_streak(&self) -> usize {
+        let mut best = 0;
+        let mut run = 0;
+        let mut previous: Option<Day> = None;
+        for result in &self.results {
+            run = match previous {
+                Some(day) if day.0 + 1 == result.day.0 => run + 1,
+                _ => 1,
+            };
+            best = best.max(run);
+            previous = Some(result.day);
+        }
+        best
+    }
+}
+
+// Boards other than 4x4 can't be daily games, they fall back to classic.
+fn start_daily(
+    mut commands: Commands,
+    mut mode: ResMut<GameMode>,
+    history: Res<DailyHistory>,
+    config: Res<Config>,
+) {
+    if *mode != GameMode::Daily {
+        commands.remove_resource::<DailyGame>();
+        return;
+    }
+    if config.size != SIZE {
+        *mode = GameMode::Classic;
+        commands.remove_resource::<DailyGame>();
+        return;
+    }
+    let day = Day::today();
+    let practice = history.get(day).is_some();
+    commands.insert_resource(DailyGame { day, practice });
+}
+
+// The attempt is used by its first move, so looking at the board costs nothing.
+fn record_daily(
+    daily: Res<DailyGame>,
+    mut history: ResMut<DailyHistory>,
+    game: Res<Game>,
+    record: Res<GameRecord>,
+) {
+    if daily.practice || record.recording.moves.is_empty() {
+        return;
+    }
+    let result = DailyResult {
+        day: daily.day,
+        score: game.score,
+        max_tile: game.max_tile(),
+        moves: record.recording.moves.len(),
+    };
+    match history
+        .results
+        .iter_mut()
+        .find(|result| result.day == daily.day)
+    {
+        Some(stored) => *stored = result,
+        // a daily started before midnight can be first played after it
+        None => {
+            let at = history
+                .results
+                .partition_point(|other| other.day < result.day);
+            history.results.insert(at, result);
+        }
+    }
+    history.save();
+}
+
+// This month with the days played filled in and today outlined, then the streaks.
+pub fn spawn_calendar(
+    parent: &mut ChildBuilder,
+    history: &DailyHistory,
+    font: &PieceFont,
+    palette: &Palette,
+) {
+    let today = Day::today();
+    let (year, month, day) = today.date();
+    let first = Day(today.0 - i64::from(day) + 1);
+    let style = |font_size, color| TextStyle {
+        font: font.0.clone_weak(),
+        font_size,
+        color,
+    };
+    parent.spawn(TextBundle::from_section(
+        format!("{} {year}", MONTHS[month as usize - 1]),
+        style(40.0, palette.text),
+    ));
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                display: Display::Grid,
+                grid_template_columns: RepeatedGridTrack::px(7, 40.0),
+                grid_auto_rows: GridTrack::px(40.0),
+                column_gap: Val::Px(6.0),
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for name in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+                parent.spawn(TextBundle::from_section(
+                    name,
+                    style(20.0, palette.text_dim),
+                ));
+            }
+            let mut day = first;
+            while day.date().1 == month {
+                let played = history.get(day).is_some();
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            grid_column: GridPlacement::start(day.weekday() as i16 + 1),
+                            border: UiRect::all(Val::Px(3.0)),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        background_color: if played {
+                            palette.accent
+                        } else {
+                            palette.button
+                        }
+                        .into(),
+                        border_color: if day == today {
+                            palette.text
+                        } else {
+                            Color::NONE
+                        }
+                        .into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            day.date().2.to_string(),
+                            style(20.0, palette.background),
+                        ));
+                    });
+                day.0 += 1;
+            }
+        });
+    let today_result = match history.get(today) {
+        Some(result) => format!(
+            "Today: score {}  max {}  {} moves",
+            result.score, result.max_tile, result.moves
+        ),
+        None => "Today: not played yet".to_string(),
+    };
+    parent.spawn(TextBundle::from_section(
+        today_result,
+        style(30.0, palette.text_dim),
+    ));
+    parent.spawn(TextBundle::from_section(
+        format!(
+            "Streak: {}  best {}",
+            history.streak(today),
+            history.best_streak()
+        ),
+        style(30.0, palette.text_dim),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(Day(0).date(), (1970, 1, 1));
+        assert_eq!(Day(0).weekday(), 3);
+        assert_eq!(Day(19_782).date(), (2024, 2, 29));
+        assert_eq!(Day(19_782).weekday(), 3);
+        assert_eq!(Day(11_016).date(), (2000, 2, 29));
+        assert_eq!(Day(11_017).date(), (2000, 3, 1));
+        assert_eq!(Day(11_017).weekday(), 2);
+        assert_eq!(Day(-1).date(), (1969, 12, 31));
+        assert_eq!(Day(-1).weekday(), 2);
+    }
+
+    // Stands in for `new_game_event`, in the order that used to lose the result.
+    fn reset_game(mut game: ResMut<Game>, mut record: ResMut<GameRecord>) {
+        game.0 = GameState::new(SIZE, 1);
+        record.recording.moves.clear();
+    }
+
+    #[test]
+    fn new_game_keeps_the_daily_result() {
+        let day = Day::today();
+        let result = DailyResult {
+            day,
+            score: 1234,
+            max_tile: 128,
+            moves: 80,
+        };
+        let mut app = App::new();
+        app.add_event::<NewGameEvent>()
+            .add_event::<AddPieceEvent>()
+            .add_plugins(DailyPlugin)
+            .insert_resource(DailyHistory {
+                results: vec![result],
+            })
+            .insert_resource(DailyGame {
+                day,
+                practice: false,
+            })
+            .insert_resource(Game(new_game(day)))
+            .insert_resource(GameRecord::default())
+            .insert_resource(GameMode::Classic)
+            .insert_resource(Config::default())
+            .add_systems(
+                Update,
+                reset_game
+                    .before(record_daily)
+                    .run_if(on_event::<NewGameEvent>()),
+            );
+        app.world_mut().send_event(NewGameEvent);
+        app.world_mut().send_event(AddPieceEvent(2));
+        app.update();
+        let stored = app.world().resource::<DailyHistory>().get(day).copied();
+        assert_eq!(stored.map(|stored| stored.score), Some(1234));
+        assert_eq!(stored.map(|stored| stored.moves), Some(80));
+        assert!(app.world().get_resource::<DailyGame>().is_none());
+    }
+
+    #[test]
+    fn looking_at_the_daily_keeps_the_attempt() {
+        let day = Day::today();
+        let mut app = App::new();
+        app.add_event::<NewGameEvent>()
+            .add_event::<AddPieceEvent>()
+            .add_plugins(DailyPlugin)
+            .insert_resource(DailyHistory::default())
+            .insert_resource(DailyGame {
+                day,
+                practice: false,
+            })
+            .insert_resource(Game(new_game(day)))
+            .insert_resource(GameRecord::default());
+        app.world_mut().send_event(AddPieceEvent(2));
+        app.update();
+        assert!(app.world().resource::<DailyHistory>().get(day).is_none());
+    }
+
+    #[test]
+    fn seed_spells_the_date() {
+        assert_eq!(Day(19_782).seed(), 20_240_229);
+        assert_eq!(Day(19_782).to_string(), "2024-02-29");
+    }
+}
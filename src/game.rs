@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+const FOUR_CHANCE: f32 = 0.1;
+
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MoveEvent {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+impl MoveEvent {
+    pub const ALL: [MoveEvent; 4] = [
+        MoveEvent::Up,
+        MoveEvent::Down,
+        MoveEvent::Right,
+        MoveEvent::Left,
+    ];
+
+    // (first cell of the first line, direction tiles slide towards, step to the next line)
+    fn lines(self, size: i32) -> (IVec2, IVec2, IVec2) {
+        match self {
+            MoveEvent::Up => ((0, 0).into(), (0, 1).into(), (1, 0).into()),
+            MoveEvent::Down => ((0, size - 1).into(), (0, -1).into(), (1, 0).into()),
+            MoveEvent::Left => ((0, 0).into(), (1, 0).into(), (0, 1).into()),
+            MoveEvent::Right => ((size - 1, 0).into(), (-1, 0).into(), (0, 1).into()),
+        }
+    }
+}
+
+// A tile that survives the move, going from `from` to `to` (possibly the same cell).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slide {
+    pub from: IVec2,
+    pub to: IVec2,
+}
+
+// A tile that slides from `from` to `to` and disappears into the tile that started at
+// `target`, which now holds `value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Merge {
+    pub from: IVec2,
+    pub to: IVec2,
+    pub target: IVec2,
+    pub value: i32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MoveOutcome {
+    pub slides: Vec<Slide>,
+    pub merges: Vec<Merge>,
+    pub score: i32,
+}
+
+impl MoveOutcome {
+    pub fn is_noop(&self) -> bool {
+        self.merges.is_empty() && self.slides.iter().all(|slide| slide.from == slide.to)
+    }
+}
+
+// Every spawn gets its own generator derived from the seed and the spawn count, so the
+// whole random state is two integers that can be copied, stored and restored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpawnRng {
+    pub seed: u64,
+    pub count: u64,
+}
+
+impl SpawnRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, count: 0 }
+    }
+    fn next(&mut self) -> StdRng {
+        self.count += 1;
+        let salt = self.count.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        StdRng::seed_from_u64(self.seed ^ salt)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameState {
+    pub size: i32,
+    pub cells: Vec<Option<i32>>,
+    pub score: i32,
+    pub rng: SpawnRng,
+    // saves from before the chance was configurable always had the default
+    #[serde(default = "default_four_chance")]
+    pub four_chance: f32,
+    // puzzles put these tiles down in order instead of random ones, and nothing once
+    // they run out
+    #[serde(default)]
+    pub script: Option<VecDeque<(IVec2, i32)>>,
+}
+
+impl GameState {
+    pub fn new(size: i32, seed: u64) -> Self {
+        Self {
+            size,
+            cells: vec![None; (size * size) as usize],
+            score: 0,
+            rng: SpawnRng::new(seed),
+            four_chance: FOUR_CHANCE,
+            script: None,
+        }
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<i32> {
+        self.cells[to_index(pos, self.size) as usize]
+    }
+
+    pub fn set(&mut self, pos: IVec2, value: Option<i32>) {
+        let index = to_index(pos, self.size) as usize;
+        self.cells[index] = value;
+    }
+
+    pub fn max_tile(&self) -> i32 {
+        self.cells.iter().flatten().copied().max().unwrap_or(0)
+    }
+
+    pub fn empty_cells(&self) -> Vec<IVec2> {
+        (0..self.cells.len())
+            .filter(|i| self.cells[*i].is_none())
+            .map(|i| to_pos(i as i32, self.size))
+            .collect()
+    }
+
+    // Puts a 2 (or, with `four_chance`, a 4) on a random empty cell, or the next tile of
+    // the script on its cell. A scripted tile whose cell is taken goes on the first empty one.
+    pub fn spawn(&mut self) -> Option<(IVec2, i32)> {
+        let mut empties = self.empty_cells();
+        if empties.is_empty() {
+            return None;
+        }
+        if let Some(script) = &mut self.script {
+            let (pos, value) = script.pop_front()?;
+            let pos = if empties.contains(&pos) {
+                pos
+            } else {
+                empties[0]
+            };
+            self.set(pos, Some(value));
+            return Some((pos, value));
+        }
+        let mut rng = self.rng.next();
+        let pos = empties.remove(rng.gen_range(0..empties.len()));
+        let value = if rng.gen_bool(self.four_chance as f64) {
+            4
+        } else {
+            2
+        };
+        self.set(pos, Some(value));
+        Some((pos, value))
+    }
+
+    pub fn apply_move(&mut self, event: MoveEvent) -> MoveOutcome {
+        let mut outcome = MoveOutcome::default();
+        let (mut start, dir, step) = event.lines(self.size);
+        for _ in 0..self.size {
+            self.slide_line(start, dir, &mut outcome);
+            start += step;
+        }
+        self.score += outcome.score;
+        outcome
+    }
+
+    // A full turn as the game plays it: the move, then a new tile if anything moved.
+    pub fn play(&mut self, event: MoveEvent) -> MoveOutcome {
+        let outcome = self.apply_move(event);
+        if !outcome.is_noop() {
+            self.spawn();
+        }
+        outcome
+    }
+
+    // Whether `event` would change the board.
+    pub fn can_play(&self, event: MoveEvent) -> bool {
+        !self.clone().apply_move(event).is_noop()
+    }
+
+    fn slide_line(&mut self, start: IVec2, dir: IVec2, outcome: &mut MoveOutcome) {
+        // (value, cell the tile started on, already merged this move)
+        let mut stack: Vec<(i32, IVec2, bool)> = vec![];
+        let mut cur = start;
+        for _ in 0..self.size {
+            if let Some(value) = self.get(cur) {
+                let stack_len = stack.len() as i32;
+                match stack.last_mut() {
+                    Some(last) if last.0 == value && !last.2 => {
+                        last.0 *= 2;
+                        last.2 = true;
+                        outcome.merges.push(Merge {
+                            from: cur,
+                            to: start + dir * (stack_len - 1),
+                            target: last.1,
+                            value: last.0,
+                        });
+                        outcome.score += last.0;
+                    }
+                    _ => {
+                        outcome.slides.push(Slide {
+                            from: cur,
+                            to: start + dir * stack_len,
+                        });
+                        stack.push((value, cur, false));
+                    }
+                }
+                self.set(cur, None);
+            }
+            cur += dir;
+        }
+        for (i, (value, _, _)) in stack.into_iter().enumerate() {
+            self.set(start + dir * i as i32, Some(value));
+        }
+    }
+
+    pub fn can_move(&self) -> bool {
+        if self.cells.iter().any(|cell| cell.is_none()) {
+            return true;
+        }
+        for i in 0..self.size {
+            for j in 0..self.size {
+                let value = self.get((i, j).into());
+                if (i + 1 < self.size && self.get((i + 1, j).into()) == value)
+                    || (j + 1 < self.size && self.get((i, j + 1).into()) == value)
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+fn default_four_chance() -> f32 {
+    FOUR_CHANCE
+}
+
+pub fn to_index(pos: IVec2, size: i32) -> i32 {
+    pos.y * size + pos.x
+}
+pub fn to_pos(index: i32, size: i32) -> IVec2 {
+    (index % size, index / size).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `rows[y][x]`, 0 for an empty cell
+    fn board(rows: &[&[i32]]) -> GameState {
+        let mut game = GameState::new(rows.len() as i32, 0);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                game.set((x as i32, y as i32).into(), (*value > 0).then_some(*value));
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn tiles_merge_once_per_move() {
+        let mut game = board(&[&[2, 2, 2, 2], &[0; 4], &[0; 4], &[0; 4]]);
+        let outcome = game.apply_move(MoveEvent::Left);
+        assert_eq!(game.cells[..4], [Some(4), Some(4), None, None]);
+        assert_eq!(
+            outcome.merges,
+            [
+                Merge {
+                    from: IVec2::new(1, 0),
+                    to: IVec2::new(0, 0),
+                    target: IVec2::new(0, 0),
+                    value: 4,
+                },
+                Merge {
+                    from: IVec2::new(3, 0),
+                    to: IVec2::new(1, 0),
+                    target: IVec2::new(2, 0),
+                    value: 4,
+                },
+            ]
+        );
+        assert_eq!(outcome.score, 8);
+        assert_eq!(game.score, 8);
+    }
+
+    #[test]
+    fn full_board_moves_only_with_equal_neighbours() {
+        assert!(!board(&[&[2, 4], &[4, 2]]).can_move());
+        assert!(board(&[&[2, 4], &[2, 8]]).can_move());
+        assert!(board(&[&[2, 2], &[4, 8]]).can_move());
+    }
+
+    #[test]
+    fn blocked_direction_is_noop() {
+        let game = board(&[&[2, 4, 0], &[0, 0, 0], &[0, 0, 0]]);
+        assert!(game.clone().apply_move(MoveEvent::Left).is_noop());
+        assert!(game.clone().apply_move(MoveEvent::Up).is_noop());
+        assert!(game.can_play(MoveEvent::Right));
+        assert!(!game.clone().apply_move(MoveEvent::Down).is_noop());
+    }
+
+    #[test]
+    fn same_seed_spawns_the_same_tiles() {
+        let mut a = GameState::new(4, 42);
+        let mut b = GameState::new(4, 42);
+        for _ in 0..10 {
+            assert_eq!(a.spawn(), b.spawn());
+        }
+        assert_eq!(a.cells, b.cells);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_2048::game::{GameState, MoveEvent};
+
+use crate::{
+    daily::assists_allowed,
+    process_move,
+    replay::{GameRecord, ReplayPlayer},
+    restore_board_event,
+    timed::Countdown,
+    AppState, Config, Game, NewGameEvent, RedoEvent, RestoreBoardEvent, UndoEvent, Victory,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_systems(
+                Update,
+                record_move
+                    .before(process_move)
+                    .run_if(on_event::<MoveEvent>())
+                    .run_if(not(resource_exists::<ReplayPlayer>)),
+            )
+            .add_systems(Update, clear_history.run_if(on_event::<NewGameEvent>()))
+            .add_systems(
+                Update,
+                (
+                    undo_event.run_if(on_event::<UndoEvent>()),
+                    redo_event.run_if(on_event::<RedoEvent>()),
+                )
+                    // the clock can't be wound back, so taking a move back would earn
+                    // its time bonus again
+                    .run_if(not(resource_exists::<Countdown>))
+                    .run_if(assists_allowed)
+                    .before(restore_board_event),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct History {
+    undo: VecDeque<GameState>,
+    redo: Vec<(GameState, Option<MoveEvent>)>,
+    undos_used: u32,
+}
+
+fn record_move(
+    mut move_event: EventReader<MoveEvent>,
+    mut history: ResMut<History>,
+    game: Res<Game>,
+    config: Res<Config>,
+) {
+    for _ in move_event.read() {
+        history.undo.push_back(game.0.clone());
+        if history.undo.len() > config.history_len {
+            history.undo.pop_front();
+        }
+        history.redo.clear();
+    }
+}
+
+fn clear_history(mut history: ResMut<History>) {
+    *history = History::default();
+}
+
+fn undo_event(
+    mut undo_event: EventReader<UndoEvent>,
+    mut history: ResMut<History>,
+    mut game: ResMut<Game>,
+    config: Res<Config>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut victory: ResMut<Victory>,
+    mut record: ResMut<GameRecord>,
+) {
+    for _ in undo_event.read() {
+        if config
+            .undo_limit
+            .is_some_and(|limit| history.undos_used >= limit)
+        {
+            continue;
+        }
+        if let Some(state) = history.undo.pop_back() {
+            let current = std::mem::replace(&mut game.0, state);
+            history.redo.push((current, record.recording.moves.pop()));
+            history.undos_used += 1;
+            // taking back the winning move shouldn't leave the win screen pending
+            if *victory == Victory::Reached && game.max_tile() < config.target {
+                *victory = Victory::NotYet;
+            }
+            restore_event.send(RestoreBoardEvent);
+            next_state.set(AppState::Input);
+        }
+    }
+}
+
+fn redo_event(
+    mut redo_event: EventReader<RedoEvent>,
+    mut history: ResMut<History>,
+    mut game: ResMut<Game>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut record: ResMut<GameRecord>,
+) {
+    for _ in redo_event.read() {
+        if let Some((state, event)) = history.redo.pop() {
+            let current = std::mem::replace(&mut game.0, state);
+            history.undo.push_back(current);
+            record.recording.moves.extend(event);
+            restore_event.send(RestoreBoardEvent);
+            next_state.set(AppState::Input);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod ai;
+pub mod game;
+pub mod recording;
@@ -3,23 +3,67 @@
     clippy::type_complexity,
     clippy::collapsible_if
 )]
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
-use crate::ui::GameUiPlugin;
+use crate::{
+    autoplay::AutoplayPlugin,
+    buffer::BufferPlugin,
+    config::{Animation, AnimationSpeed, Config},
+    controls::{Action, Actions, ControlsPlugin},
+    daily::{DailyPlugin, Day},
+    history::HistoryPlugin,
+    menu::MenuPlugin,
+    puzzle::{CurrentPuzzle, PuzzlePlugin},
+    replay::{GameRecord, ReplayPlayer, ReplayPlugin, StartReplayEvent},
+    save::SavePlugin,
+    settings::SettingsPlugin,
+    sound::SoundPlugin,
+    swipe::SwipePlugin,
+    theme::{EmptyCell, Palette, ThemePlugin},
+    timed::TimedPlugin,
+    ui::GameUiPlugin,
+};
 use bevy::{
     asset::AssetMetaCheck,
     prelude::*,
-    utils::HashMap,
-    window::{PresentMode, WindowResolution},
+    window::{PresentMode, WindowFocused, WindowResolution},
 };
+use bevy_2048::game::{to_index, to_pos, GameState, MoveEvent};
 use bevy_aseprite_ultra::prelude::*;
 use bevy_tweening::*;
-use lens::TransformPositionLens;
+use lens::{TransformPositionLens, TransformScaleLens};
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 
+mod autoplay;
+mod buffer;
+mod config;
+mod controls;
+mod daily;
+mod history;
+mod menu;
+mod puzzle;
+mod replay;
+mod save;
+mod settings;
+mod sound;
+mod swipe;
+mod theme;
+mod timed;
 mod ui;
+
+// how far the board moves, and how long it takes, when a move changes nothing
+const SHAKE_DISTANCE: f32 = 12.0;
+const SHAKE_MS: u64 = 50;
+
 fn main() {
-    let config = Config::new();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
     let mut app = App::new();
     app.add_plugins(
         DefaultPlugins
@@ -41,13 +85,39 @@ fn main() {
     .add_plugins(BevySprityPlugin)
     .add_plugins(TweeningPlugin)
     .add_plugins(GameUiPlugin)
+    .add_plugins(HistoryPlugin)
+    .add_plugins(SavePlugin)
+    .add_plugins(ReplayPlugin)
+    .add_plugins(AutoplayPlugin)
+    .add_plugins(SwipePlugin)
+    .add_plugins(ControlsPlugin)
+    .add_plugins(BufferPlugin)
+    .add_plugins(SettingsPlugin)
+    .add_plugins(MenuPlugin)
+    .add_plugins(ThemePlugin)
+    .add_plugins(SoundPlugin)
+    .add_plugins(TimedPlugin)
+    .add_plugins(DailyPlugin)
+    .add_plugins(PuzzlePlugin)
+    .insert_resource(config.animation_speed)
     .insert_resource(config)
     .insert_state(AppState::Setup)
     .insert_resource(ClearColor(Color::linear_rgb(1.0, 1.0, 1.0)))
     .add_event::<AddPieceEvent>()
     .add_event::<SetValueEvent>()
     .add_event::<MoveEvent>()
+    .add_event::<MoveInputEvent>()
     .add_event::<NewGameEvent>()
+    .add_event::<KeepGoingEvent>()
+    .add_event::<UndoEvent>()
+    .add_event::<RedoEvent>()
+    .add_event::<RestoreBoardEvent>()
+    .add_event::<ResizeBoardEvent>()
+    .add_event::<InvalidMoveEvent>()
+    .init_resource::<Victory>()
+    .init_resource::<PausedState>()
+    .init_resource::<Resumed>()
+    .init_resource::<GameMode>()
     .add_systems(
         OnEnter(AppState::Setup),
         (
@@ -60,13 +130,36 @@ fn main() {
             .in_set(InitSet)
             .chain(),
     )
-    .add_systems(Update, input)
+    .add_systems(Update, (input, pause_on_focus_lost))
+    .add_systems(
+        Update,
+        shake_board
+            .run_if(on_event::<InvalidMoveEvent>())
+            .run_if(|config: Res<Config>| config.shake_invalid_moves),
+    )
+    .add_systems(Update, freeze_animations.run_if(state_changed::<AppState>))
+    .add_systems(OnExit(AppState::Won), clear_resumed)
+    .add_systems(OnExit(AppState::GameOver), clear_resumed)
     .add_systems(Update, (check_anim_end).run_if(in_state(AppState::Anim)))
     .add_systems(
         Update,
         (anim_completed_event).run_if(on_event::<TweenCompleted>()),
     )
+    .add_systems(
+        Update,
+        (resize_board, apply_deferred, create_board)
+            .chain()
+            .run_if(on_event::<ResizeBoardEvent>())
+            .before(new_game_event),
+    )
     .add_systems(Update, (new_game_event).run_if(on_event::<NewGameEvent>()))
+    // solved puzzles are over, whatever asks to keep going
+    .add_systems(
+        Update,
+        (keep_going_event)
+            .run_if(on_event::<KeepGoingEvent>())
+            .run_if(not(resource_exists::<CurrentPuzzle>)),
+    )
     .add_systems(
         Update,
         (set_board, process_move)
@@ -79,6 +172,12 @@ fn main() {
             .chain()
             .run_if(on_event::<SetValueEvent>()),
     )
+    .add_systems(
+        Update,
+        (restore_board_event, apply_deferred, check_game_end)
+            .chain()
+            .run_if(on_event::<RestoreBoardEvent>()),
+    )
     .add_systems(OnEnter(AppState::PostAnim), (set_board, post_anim).chain())
     //.add_systems(OnEnter(AppState::Input), check_game_end)
     .add_systems(
@@ -89,18 +188,12 @@ fn main() {
     );
     app.run();
 }
-fn setup(
-    mut commands: Commands,
-    mut add_event: EventWriter<AddPieceEvent>,
-    asset_server: Res<AssetServer>,
-    config: Res<Config>,
-) {
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<Config>) {
     commands.spawn((Camera2dBundle {
         transform: Transform::from_xyz(config.window_size.x, -config.window_size.y, 100.0),
         projection: OrthographicProjection { ..default() },
         ..default()
     },));
-    add_event.send(AddPieceEvent(2));
     let font = asset_server.load("mai10/mai10.ttf");
     let title_font = asset_server.load("Early GameBoy.ttf");
     let sprite = asset_server.load("sprites.aseprite");
@@ -110,168 +203,302 @@ fn setup(
     commands.insert_resource(Board {
         pieces: vec![None; (config.size * config.size) as usize],
     });
-    let pos = config.window_size - Vec2::new(config.board_size(), config.board_size()) / 2.0;
+    commands.insert_resource(Game(new_game_state(&config, GameMode::Classic)));
     let pivot = commands
         .spawn((
             TransformBundle {
-                local: Transform::from_xyz(pos.x, -pos.y, 0.0),
+                local: Transform::from_translation(board_origin(&config)),
                 ..default()
             },
             VisibilityBundle::default(),
         ))
         .id();
     commands.insert_resource(BoardPivot(pivot));
-    let mut map = HashMap::new();
-    map.insert(2, Color::srgb_u8(255, 209, 0));
-    map.insert(4, Color::srgb_u8(255, 132, 38));
-    map.insert(8, Color::srgb_u8(214, 36, 17));
-    map.insert(16, Color::srgb_u8(255, 128, 164));
-    map.insert(32, Color::srgb_u8(255, 38, 116));
-    map.insert(64, Color::srgb_u8(191, 255, 60));
-    map.insert(128, Color::srgb_u8(16, 210, 117));
-    map.insert(256, Color::srgb_u8(40, 200, 225));
-    map.insert(512, Color::srgb_u8(31, 85, 148));
-    map.insert(1024, Color::srgb_u8(67, 0, 103));
-    map.insert(2048, Color::srgb_u8(148, 33, 106));
-    map.insert(4096, Color::srgb_u8(155, 124, 68));
-    map.insert(8192, Color::srgb_u8(199, 113, 244));
-    map.insert(16384, Color::srgb_u8(103, 40, 225));
-    map.insert(32768, Color::srgb_u8(237, 198, 131));
-    map.insert(65536, Color::srgb_u8(144, 52, 192));
-
-    commands.insert_resource(ColorMap { map });
     commands.insert_resource(Score(0));
     commands.insert_resource(ScoreToAdd(0));
-    commands.insert_resource(HighScore(0));
+    commands.insert_resource(HighScores::default());
+}
+// Top left corner of the board, which is centered on the camera.
+fn board_origin(config: &Config) -> Vec3 {
+    let pos = config.window_size - Vec2::new(config.board_size(), config.board_size()) / 2.0;
+    Vec3::new(pos.x, -pos.y, 0.0)
+}
+fn new_game_state(config: &Config, mode: GameMode) -> GameState {
+    if mode == GameMode::Daily && config.size == daily::SIZE {
+        return daily::new_game(Day::today());
+    }
+    let seed = config
+        .seed
+        .unwrap_or_else(|| thread_rng().gen::<u32>() as u64);
+    GameState {
+        four_chance: config.spawn_four_chance,
+        ..GameState::new(config.size, seed)
+    }
 }
 fn process_move(
     mut commands: Commands,
     mut move_event: EventReader<MoveEvent>,
-    mut board: ResMut<Board>,
+    board: Res<Board>,
+    mut game: ResMut<Game>,
     config: Res<Config>,
+    speed: Res<AnimationSpeed>,
     mut next_state: ResMut<NextState<AppState>>,
     mut score_to_add: ResMut<ScoreToAdd>,
+    mut pieces: Query<(&mut Transform, &mut Pos, &mut Value)>,
+    mut set_value_event: EventWriter<SetValueEvent>,
+    mut victory: ResMut<Victory>,
+    replay: Option<Res<ReplayPlayer>>,
 ) {
     for event in move_event.read() {
-        let dir;
-        let start_dir;
-        let start;
-        let size = config.size;
-        match event {
-            MoveEvent::Up => {
-                dir = (0, 1);
-                start = (0, 0);
-                start_dir = (1, 0);
-            }
-            MoveEvent::Down => {
-                dir = (0, -1);
-                start = (0, size - 1);
-                start_dir = (1, 0);
+        let outcome = game.apply_move(*event);
+        // player moves are checked before they get here, this catches old replays
+        if outcome.is_noop() {
+            continue;
+        }
+        score_to_add.0 += outcome.score;
+        // instant mode puts every piece where it ends up and skips Anim altogether
+        let Some(multiplier) = speed.multiplier() else {
+            for slide in &outcome.slides {
+                let entity = board.pieces[to_index(slide.from, config.size) as usize];
+                if let Some(Ok((mut transform, mut pos, _))) = entity.map(|e| pieces.get_mut(e)) {
+                    transform.translation = pos_to_world(slide.to, &config).extend(2.0);
+                    *pos = Pos(slide.to);
+                }
             }
-            MoveEvent::Left => {
-                dir = (1, 0);
-                start = (0, 0);
-                start_dir = (0, 1);
+            for merge in &outcome.merges {
+                let entity = board.pieces[to_index(merge.from, config.size) as usize];
+                let target = board.pieces[to_index(merge.target, config.size) as usize];
+                if let (Some(entity), Some(target)) = (entity, target) {
+                    commands.entity(entity).despawn_recursive();
+                    if let Ok((_, _, mut value)) = pieces.get_mut(target) {
+                        *value = Value(merge.value);
+                    }
+                    set_value_event.send(SetValueEvent {
+                        entity: target,
+                        value: merge.value,
+                    });
+                    check_victory(merge.value, &config, &mut victory, replay.is_some());
+                }
             }
-            MoveEvent::Right => {
-                dir = (-1, 0);
-                start = (size - 1, 0);
-                start_dir = (0, 1);
+            next_state.set(AppState::PostAnim);
+            continue;
+        };
+        for slide in &outcome.slides {
+            if let Some(entity) = board.pieces[to_index(slide.from, config.size) as usize] {
+                commands.entity(entity).insert((
+                    MoveType::Move(slide.to),
+                    slide_animator(slide.from, slide.to, &config).with_speed(multiplier),
+                ));
             }
         }
-        let mut cur = start;
-        for _ in 0..size {
-            update_line(
-                &mut commands,
-                &mut board,
-                cur.into(),
-                dir.into(),
-                &config,
-                &mut score_to_add,
-            );
-            cur.0 += start_dir.0;
-            cur.1 += start_dir.1;
+        for merge in &outcome.merges {
+            let entity = board.pieces[to_index(merge.from, config.size) as usize];
+            let target = board.pieces[to_index(merge.target, config.size) as usize];
+            if let (Some(entity), Some(target)) = (entity, target) {
+                commands.entity(entity).insert((
+                    MoveType::MoveDouble((merge.to, target)),
+                    slide_animator(merge.from, merge.to, &config).with_speed(multiplier),
+                ));
+            }
         }
         next_state.set(AppState::Anim);
     }
 }
 
+// Reaching the target only counts once, and not while watching a replay.
+fn check_victory(value: i32, config: &Config, victory: &mut Victory, replaying: bool) {
+    if value == config.target && *victory == Victory::NotYet && !replaying {
+        *victory = Victory::Reached;
+    }
+}
 fn input(
-    keys: Res<ButtonInput<KeyCode>>,
-    mut move_event: EventWriter<MoveEvent>,
+    actions: Actions,
+    mut move_input: EventWriter<MoveInputEvent>,
     mut new_game: EventWriter<NewGameEvent>,
+    mut keep_going: EventWriter<KeepGoingEvent>,
+    mut undo: EventWriter<UndoEvent>,
+    mut redo: EventWriter<RedoEvent>,
+    mut replay: EventWriter<StartReplayEvent>,
+    record: Res<GameRecord>,
     state: Res<State<AppState>>,
+    mut speed: ResMut<AnimationSpeed>,
+    mut paused: ResMut<PausedState>,
+    mut next_state: ResMut<NextState<AppState>>,
 ) {
-    if *state.get() == AppState::Input {
-        if keys.just_pressed(KeyCode::KeyW) || keys.just_pressed(KeyCode::ArrowUp) {
-            move_event.send(MoveEvent::Up);
-        } else if keys.just_pressed(KeyCode::KeyA) || keys.just_pressed(KeyCode::ArrowLeft) {
-            move_event.send(MoveEvent::Left);
-        } else if keys.just_pressed(KeyCode::KeyS) || keys.just_pressed(KeyCode::ArrowDown) {
-            move_event.send(MoveEvent::Down);
-        } else if keys.just_pressed(KeyCode::KeyD) || keys.just_pressed(KeyCode::ArrowRight) {
-            move_event.send(MoveEvent::Right);
+    if actions.just_pressed(Action::AnimationSpeed) {
+        *speed = speed.next();
+    }
+    // replays have their own pause, and Escape already closes them
+    if actions.just_pressed(Action::Pause) && *state.get() != AppState::Replay {
+        if *state.get() == AppState::Paused {
+            next_state.set(paused.0.clone());
+        } else {
+            pause(state.get(), &mut paused, &mut next_state);
+        }
+    }
+    if actions.just_pressed(Action::Up) {
+        move_input.send(MoveInputEvent(MoveEvent::Up));
+    } else if actions.just_pressed(Action::Left) {
+        move_input.send(MoveInputEvent(MoveEvent::Left));
+    } else if actions.just_pressed(Action::Down) {
+        move_input.send(MoveInputEvent(MoveEvent::Down));
+    } else if actions.just_pressed(Action::Right) {
+        move_input.send(MoveInputEvent(MoveEvent::Right));
+    }
+    if *state.get() == AppState::Won {
+        if actions.just_pressed(Action::KeepGoing) {
+            keep_going.send(KeepGoingEvent);
         }
     }
-    if *state.get() == AppState::Input || *state.get() == AppState::GameOver {
-        if keys.just_pressed(KeyCode::KeyR) {
+    if *state.get() == AppState::Paused {
+        if actions.just_pressed(Action::NewGame) {
             new_game.send(NewGameEvent);
         }
     }
+    if *state.get() == AppState::Input
+        || *state.get() == AppState::GameOver
+        || *state.get() == AppState::Won
+    {
+        if actions.just_pressed(Action::NewGame) {
+            new_game.send(NewGameEvent);
+        }
+        if actions.just_pressed(Action::Undo) {
+            undo.send(UndoEvent);
+        } else if actions.just_pressed(Action::Redo) {
+            redo.send(RedoEvent);
+        }
+        if actions.just_pressed(Action::Replay) && record.replayable {
+            replay.send(StartReplayEvent {
+                recording: record.recording.clone(),
+                resume: true,
+            });
+        }
+    }
 }
-fn update_line(
-    commands: &mut Commands,
-    board: &mut Board,
-    start: IVec2,
-    dir: IVec2,
-    config: &Config,
-    score_to_add: &mut ScoreToAdd,
+
+// Only states that can be picked up again as they were: entering Won or GameOver twice
+// would redo their side effects.
+fn pause(state: &AppState, paused: &mut PausedState, next_state: &mut NextState<AppState>) {
+    if matches!(state, AppState::Input | AppState::Anim | AppState::Replay) {
+        paused.0 = state.clone();
+        next_state.set(AppState::Paused);
+    }
+}
+
+// Menus and settings go back to where they were opened from. Won and GameOver are
+// entered again for their overlays, with `Resumed` set so their other side effects
+// don't happen twice for the same game.
+fn resume(state: &AppState, resumed: &mut Resumed, next_state: &mut NextState<AppState>) {
+    resumed.0 = matches!(state, AppState::Won | AppState::GameOver);
+    next_state.set(state.clone());
+}
+
+fn not_resumed(resumed: Res<Resumed>) -> bool {
+    !resumed.0
+}
+
+fn clear_resumed(mut resumed: ResMut<Resumed>) {
+    resumed.0 = false;
+}
+
+fn pause_on_focus_lost(
+    mut focus_event: EventReader<WindowFocused>,
+    state: Res<State<AppState>>,
+    mut paused: ResMut<PausedState>,
+    mut next_state: ResMut<NextState<AppState>>,
 ) {
-    let size = config.size;
-    let mut cur = start;
-    let mut stack: Vec<(Piece, bool)> = vec![];
-    for _ in 0..size {
-        let stack_len = stack.len();
-        if let Some(piece) = board.pieces[to_index(cur, size) as usize] {
-            let pos;
-            if let Some(last) = stack.last_mut() {
-                if last.0.value == piece.value && !last.1 {
-                    last.0.value *= 2;
-                    last.1 = true;
-                    pos = start + dir * (stack_len as i32 - 1);
-                    commands
-                        .entity(piece.entity)
-                        .insert(MoveType::MoveDouble((pos, last.0.entity)));
-                    score_to_add.0 += last.0.value;
-                } else {
-                    pos = start + dir * (stack.len() as i32);
-                    commands.entity(piece.entity).insert(MoveType::Move(pos));
-                    stack.push((piece, false));
-                }
-            } else {
-                pos = start;
-                commands.entity(piece.entity).insert(MoveType::Move(start));
-                stack.push((piece, false));
-            }
-            let start = pos_to_world(piece.pos, config);
-            let end = pos_to_world(pos, config);
-            let d = (end - start).length();
-            let t = d / 4000.0;
-            let tween = Tween::new(
-                EaseMethod::Linear,
-                Duration::from_secs_f32(t + 0.01),
-                TransformPositionLens {
-                    start: start.extend(2.0),
-                    end: end.extend(2.0),
-                },
-            )
-            .with_completed_event(0);
-            commands.entity(piece.entity).insert(Animator::new(tween));
-        }
-        cur += dir;
+    if focus_event.read().any(|event| !event.focused) {
+        pause(state.get(), &mut paused, &mut next_state);
+    }
+}
+
+// Tweens stand still while the game is covered by the pause overlay or a menu.
+fn freeze_animations(state: Res<State<AppState>>, mut animators: Query<&mut Animator<Transform>>) {
+    let frozen = matches!(
+        state.get(),
+        AppState::Paused | AppState::Menu | AppState::Settings
+    );
+    for mut animator in animators.iter_mut() {
+        animator.state = if frozen {
+            AnimatorState::Paused
+        } else {
+            AnimatorState::Playing
+        };
     }
 }
 
+fn slide_animator(from: IVec2, to: IVec2, config: &Config) -> Animator<Transform> {
+    let start = pos_to_world(from, config);
+    let end = pos_to_world(to, config);
+    let cells = (to - from).abs().max_element() as u64;
+    let animation = &config.slide_animation;
+    let tween = Tween::new(
+        animation.ease.method(),
+        Duration::from_millis(cells * animation.duration_ms + 10),
+        TransformPositionLens {
+            start: start.extend(2.0),
+            end: end.extend(2.0),
+        },
+    )
+    .with_completed_event(0);
+    Animator::new(tween)
+}
+
+// Scales go on the sprite and text of a piece, its own transform is used for sliding.
+fn spawn_animator(animation: &Animation) -> Animator<Transform> {
+    Animator::new(Tween::new(
+        animation.ease.method(),
+        Duration::from_millis(animation.duration_ms.max(1)),
+        TransformScaleLens {
+            start: Vec3::ZERO,
+            end: Vec3::ONE,
+        },
+    ))
+}
+
+fn pop_animator(animation: &Animation) -> Animator<Transform> {
+    let tween = Tween::new(
+        animation.ease.method(),
+        Duration::from_millis((animation.duration_ms / 2).max(1)),
+        TransformScaleLens {
+            start: Vec3::ONE,
+            end: Vec3::splat(1.2),
+        },
+    )
+    .with_repeat_count(RepeatCount::Finite(2))
+    .with_repeat_strategy(RepeatStrategy::MirroredRepeat);
+    Animator::new(tween)
+}
+
+// Nudges the board towards a move that changes nothing and back.
+fn shake_board(
+    mut commands: Commands,
+    mut invalid_event: EventReader<InvalidMoveEvent>,
+    config: Res<Config>,
+    speed: Res<AnimationSpeed>,
+    pivot: Res<BoardPivot>,
+) {
+    let (Some(InvalidMoveEvent(event)), Some(multiplier)) =
+        (invalid_event.read().last(), speed.multiplier())
+    else {
+        return;
+    };
+    let origin = board_origin(&config);
+    let tween = Tween::new(
+        EaseFunction::QuadraticOut,
+        Duration::from_millis(SHAKE_MS),
+        TransformPositionLens {
+            start: origin,
+            end: origin + (move_direction(*event) * SHAKE_DISTANCE).extend(0.0),
+        },
+    )
+    .with_repeat_count(RepeatCount::Finite(2))
+    .with_repeat_strategy(RepeatStrategy::MirroredRepeat);
+    commands
+        .entity(pivot.0)
+        .insert(Animator::new(tween).with_speed(multiplier));
+}
+
 fn pos_to_world(pos: IVec2, config: &Config) -> Vec2 {
     (
         (pos.x * config.tile_size + config.tile_size / 2 + config.pad + 2 * pos.x * config.pad)
@@ -281,67 +508,72 @@ fn pos_to_world(pos: IVec2, config: &Config) -> Vec2 {
     )
         .into()
 }
+// The way tiles go on screen for a move.
+fn move_direction(event: MoveEvent) -> Vec2 {
+    match event {
+        MoveEvent::Up => Vec2::Y,
+        MoveEvent::Down => Vec2::NEG_Y,
+        MoveEvent::Right => Vec2::X,
+        MoveEvent::Left => Vec2::NEG_X,
+    }
+}
 fn add_piece_event(
     mut commands: Commands,
     mut add_event: EventReader<AddPieceEvent>,
     mut board: ResMut<Board>,
+    mut game: ResMut<Game>,
     config: Res<Config>,
+    speed: Res<AnimationSpeed>,
     font: Res<PieceFont>,
     sprite: Res<SpriteHandle>,
     pivot: Res<BoardPivot>,
-    color_map: Res<ColorMap>,
+    palette: Res<Palette>,
 ) {
-    let mut empties = vec![];
-    for i in 0..board.pieces.len() {
-        if board.pieces[i].is_none() {
-            empties.push(i);
-        }
-    }
-    if empties.is_empty() {
-        return;
-    }
-    let mut rng = thread_rng();
     for event in add_event.read() {
         for _ in 0..event.0 {
-            let pos = empties.remove(rng.gen_range(0..empties.len()));
-            let value = if rng.gen_range(0..10) == 0 { 4 } else { 2 };
-            create_piece(
-                &mut commands,
-                to_pos(pos as i32, config.size),
-                value,
-                &config,
-                font.0.clone_weak(),
-                sprite.0.clone_weak(),
-                &pivot,
-                &color_map,
-                &mut board,
-            );
+            if let Some((pos, value)) = game.spawn() {
+                create_piece(
+                    &mut commands,
+                    pos,
+                    value,
+                    &config,
+                    font.0.clone_weak(),
+                    sprite.0.clone_weak(),
+                    &pivot,
+                    &palette,
+                    &mut board,
+                    speed.multiplier(),
+                );
+            }
         }
     }
 }
 
-fn to_index(pos: IVec2, size: i32) -> i32 {
-    pos.y * size + pos.x
-}
-fn to_pos(index: i32, size: i32) -> IVec2 {
-    (index % size, index / size).into()
-}
 fn create_board(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     config: Res<Config>,
     pivot: Res<BoardPivot>,
+    palette: Res<Palette>,
 ) {
     for i in 0..config.size {
         for j in 0..config.size {
             let world_pos = pos_to_world((i, j).into(), &config);
             commands
-                .spawn(AsepriteSliceBundle {
-                    slice: "back".into(),
-                    aseprite: asset_server.load("sprites.aseprite"),
-                    transform: Transform::from_xyz(world_pos.x, world_pos.y, 2.0),
-                    ..default()
-                })
+                .spawn((
+                    AsepriteSliceBundle {
+                        slice: "back".into(),
+                        aseprite: asset_server.load("sprites.aseprite"),
+                        transform: Transform::from_xyz(world_pos.x, world_pos.y, 2.0)
+                            .with_scale(config.tile_scale()),
+                        sprite: Sprite {
+                            color: palette.empty_cell,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    EmptyCell,
+                ))
                 .set_parent(pivot.0);
         }
     }
@@ -355,42 +587,52 @@ fn create_piece(
     font: Handle<Font>,
     sprite: Handle<Aseprite>,
     pivot: &BoardPivot,
-    color_map: &ColorMap,
+    palette: &Palette,
     board: &mut Board,
+    spawn_speed: Option<f32>,
 ) {
-    let color = match color_map.map.get(&value) {
-        Some(c) => *c,
-        None => Color::BLACK,
-    };
+    let tile = palette.tile(value);
     let base = commands
         .spawn(AsepriteSliceBundle {
             transform: Transform::from_xyz(0.0, 0.0, 2.0),
             slice: "piece".into(),
             aseprite: sprite,
-            sprite: Sprite { color, ..default() },
+            sprite: Sprite {
+                color: tile.color,
+                ..default()
+            },
             ..default()
         })
         .id();
+    let label = tile_label(value, config);
     let text = commands
         .spawn(Text2dBundle {
             transform: Transform::from_xyz(0.0, 0.0, 4.0),
             text: Text::from_section(
-                value.to_string(),
+                label.clone(),
                 TextStyle {
                     font,
-                    font_size: 48.0,
-                    color: Color::WHITE,
+                    font_size: palette.tile_font_size(&label),
+                    color: tile.text,
                 },
             ),
             ..default()
         })
         .id();
+    if let Some(speed) = spawn_speed {
+        for child in [base, text] {
+            commands
+                .entity(child)
+                .insert(spawn_animator(&config.spawn_animation).with_speed(speed));
+        }
+    }
     let world_pos = pos_to_world(pos, config);
     let piece = commands
         .spawn((
             PieceMarker,
             TransformBundle {
-                local: Transform::from_xyz(world_pos.x, world_pos.y, 2.0),
+                local: Transform::from_xyz(world_pos.x, world_pos.y, 2.0)
+                    .with_scale(config.tile_scale()),
                 ..default()
             },
             VisibilityBundle { ..default() },
@@ -400,25 +642,50 @@ fn create_piece(
         .push_children(&[base, text])
         .set_parent(pivot.0)
         .id();
-    board.pieces[to_index(pos, config.size) as usize] = Some(Piece {
-        entity: piece,
-        value,
-        pos,
-    });
+    board.pieces[to_index(pos, config.size) as usize] = Some(piece);
 }
 
-fn set_board(query: Query<(Entity, &Value, &Pos)>, mut board: ResMut<Board>, config: Res<Config>) {
-    for i in 0..config.size * config.size {
-        board.pieces[i as usize] = None;
+fn set_board(query: Query<(Entity, &Pos)>, mut board: ResMut<Board>, config: Res<Config>) {
+    board.pieces.fill(None);
+    for (entity, pos) in query.iter() {
+        board.pieces[to_index(pos.0, config.size) as usize] = Some(entity);
     }
-
-    for (entity, value, pos) in query.iter() {
-        board.pieces[to_index(pos.0, config.size) as usize] = Some(Piece {
-            entity,
-            value: value.0,
-            pos: pos.0,
-        });
+}
+fn restore_board_event(
+    mut commands: Commands,
+    mut board: ResMut<Board>,
+    game: Res<Game>,
+    config: Res<Config>,
+    font: Res<PieceFont>,
+    sprite: Res<SpriteHandle>,
+    pivot: Res<BoardPivot>,
+    palette: Res<Palette>,
+    query: Query<Entity, With<PieceMarker>>,
+    mut score: ResMut<Score>,
+    mut score_to_add: ResMut<ScoreToAdd>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
     }
+    board.pieces.fill(None);
+    for (i, cell) in game.cells.iter().enumerate() {
+        if let Some(value) = cell {
+            create_piece(
+                &mut commands,
+                to_pos(i as i32, config.size),
+                *value,
+                &config,
+                font.0.clone_weak(),
+                sprite.0.clone_weak(),
+                &pivot,
+                &palette,
+                &mut board,
+                None,
+            );
+        }
+    }
+    score.0 = game.score;
+    score_to_add.0 = 0;
 }
 fn check_anim_end(
     query: Query<Entity, With<MoveType>>,
@@ -436,6 +703,11 @@ fn anim_completed_event(
     mut pos_query: Query<&mut Pos>,
     mut value_query: Query<&mut Value>,
     mut set_value_event: EventWriter<SetValueEvent>,
+    children_query: Query<&Children>,
+    speed: Res<AnimationSpeed>,
+    mut victory: ResMut<Victory>,
+    config: Res<Config>,
+    replay: Option<Res<ReplayPlayer>>,
 ) {
     for event in anim_event.read() {
         if let Ok((entity, move_type)) = query.get(event.entity) {
@@ -449,11 +721,19 @@ fn anim_completed_event(
                 MoveType::MoveDouble((_pos, target)) => {
                     if let Ok(mut target_value) = value_query.get_mut(*target) {
                         target_value.0 *= 2;
+                        check_victory(target_value.0, &config, &mut victory, replay.is_some());
                         set_value_event.send(SetValueEvent {
                             entity: *target,
                             value: target_value.0,
                         });
                     }
+                    if let Some(multiplier) = speed.multiplier() {
+                        for child in children_query.iter_descendants(*target) {
+                            commands.entity(child).insert(
+                                pop_animator(&config.merge_animation).with_speed(multiplier),
+                            );
+                        }
+                    }
                     commands.entity(entity).despawn_recursive();
                 }
             }
@@ -461,29 +741,40 @@ fn anim_completed_event(
     }
 }
 
+// "2^17" instead of "131072" once tiles reach `exponent_labels`.
+fn tile_label(value: i32, config: &Config) -> String {
+    match config.exponent_labels {
+        Some(from) if value >= from => format!("2^{}", value.ilog2()),
+        _ => value.to_string(),
+    }
+}
+
 fn set_value_event(
     mut set_value_event: EventReader<SetValueEvent>,
     query: Query<&Children>,
     mut text_query: Query<&mut Text>,
     mut sprite_query: Query<&mut Sprite>,
     font: Res<PieceFont>,
-    color_map: Res<ColorMap>,
+    palette: Res<Palette>,
+    config: Res<Config>,
 ) {
     for event in set_value_event.read() {
+        let tile = palette.tile(event.value);
+        let label = tile_label(event.value, &config);
         if let Ok(children) = query.get(event.entity) {
             for child in children.iter() {
                 if let Ok(mut text) = text_query.get_mut(*child) {
                     *text = Text::from_section(
-                        event.value.to_string(),
+                        label.clone(),
                         TextStyle {
                             font: font.0.clone_weak(),
-                            font_size: 50.0,
-                            color: Color::WHITE,
+                            font_size: palette.tile_font_size(&label),
+                            color: tile.text,
                         },
                     );
                 }
                 if let Ok(mut sprite) = sprite_query.get_mut(*child) {
-                    sprite.color = color_map.map[&event.value];
+                    sprite.color = tile.color;
                 }
             }
         }
@@ -495,28 +786,79 @@ fn post_anim(
     mut next_state: ResMut<NextState<AppState>>,
     mut score: ResMut<Score>,
     mut score_to_add: ResMut<ScoreToAdd>,
-    mut high_score: ResMut<HighScore>,
+    mut high_scores: ResMut<HighScores>,
+    mode: Res<GameMode>,
+    victory: Res<Victory>,
+    replay: Option<Res<ReplayPlayer>>,
 ) {
     add_event.send(AddPieceEvent(1));
-    next_state.set(AppState::Input);
     score.0 += score_to_add.0;
     score_to_add.0 = 0;
-    high_score.0 = high_score.0.max(score.0);
+    if replay.is_some() {
+        next_state.set(AppState::Replay);
+        return;
+    }
+    if *victory == Victory::Reached {
+        next_state.set(AppState::Won);
+    } else {
+        next_state.set(AppState::Input);
+    }
+    high_scores.update(*mode, score.0);
+}
+
+// Clears everything on the board so `create_board` can lay out the new size.
+fn resize_board(
+    mut commands: Commands,
+    mut board: ResMut<Board>,
+    config: Res<Config>,
+    pivot: Res<BoardPivot>,
+    mut transforms: Query<&mut Transform>,
+) {
+    commands
+        .entity(pivot.0)
+        .despawn_descendants()
+        .remove::<Animator<Transform>>();
+    board.pieces = vec![None; (config.size * config.size) as usize];
+    if let Ok(mut transform) = transforms.get_mut(pivot.0) {
+        transform.translation = board_origin(&config);
+    }
 }
 
 fn new_game_event(
     mut commands: Commands,
     new_game_event: EventReader<NewGameEvent>,
     mut board: ResMut<Board>,
+    mut game: ResMut<Game>,
     mut add_event: EventWriter<AddPieceEvent>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+    state: Res<State<AppState>>,
     mut next_state: ResMut<NextState<AppState>>,
     query: Query<Entity, With<PieceMarker>>,
     mut score: ResMut<Score>,
+    mut victory: ResMut<Victory>,
+    config: Res<Config>,
+    mode: Res<GameMode>,
+    puzzle: Option<Res<CurrentPuzzle>>,
 ) {
     if !new_game_event.is_empty() {
         board.pieces.fill(None);
-        add_event.send(AddPieceEvent(2));
-        next_state.set(AppState::Input);
+        game.0 = match puzzle {
+            Some(puzzle) if *mode == GameMode::Puzzle && puzzle.puzzle.size == config.size => {
+                puzzle.puzzle.start()
+            }
+            _ => new_game_state(&config, *mode),
+        };
+        *victory = Victory::NotYet;
+        // puzzles come with their tiles already laid out
+        if game.cells.iter().any(|cell| cell.is_some()) {
+            restore_event.send(RestoreBoardEvent);
+        } else {
+            add_event.send(AddPieceEvent(2));
+        }
+        // a board resized from the main menu's settings waits there for Continue
+        if *state.get() != AppState::Menu {
+            next_state.set(AppState::Input);
+        }
         for entity in query.iter() {
             commands.entity(entity).despawn_recursive();
         }
@@ -525,68 +867,54 @@ fn new_game_event(
 }
 
 fn check_game_end(
-    board: Res<Board>,
+    game: Res<Game>,
+    state: Res<State<AppState>>,
     mut next_state: ResMut<NextState<AppState>>,
-    config: Res<Config>,
+    replay: Option<Res<ReplayPlayer>>,
 ) {
-    for piece in &board.pieces {
-        if piece.is_none() {
-            return;
-        }
-    }
-    let dirs = [(0, 1), (0, -1), (1, 0), (-1, 0)];
-    for i in 0..config.size {
-        for j in 0..config.size {
-            let cur = (i, j);
-            for dir in dirs {
-                let neigh = (cur.0 + dir.0, cur.1 + dir.1);
-                if neigh.0 >= 0 && neigh.0 < config.size && neigh.1 >= 0 && neigh.1 < config.size {
-                    if let Some(p1) = board.pieces[to_index(cur.into(), config.size) as usize] {
-                        if let Some(p2) = board.pieces[to_index(neigh.into(), config.size) as usize]
-                        {
-                            if p1.value == p2.value {
-                                return;
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    // the game opens on the menu, which decides where to go when it closes
+    if !game.can_move()
+        && replay.is_none()
+        && !matches!(state.get(), AppState::Setup | AppState::Menu)
+    {
+        next_state.set(AppState::GameOver);
     }
-    next_state.set(AppState::GameOver);
 }
 
-fn start_game(mut next_state: ResMut<NextState<AppState>>) {
+fn keep_going_event(mut victory: ResMut<Victory>, mut next_state: ResMut<NextState<AppState>>) {
+    *victory = Victory::KeepGoing;
     next_state.set(AppState::Input);
 }
 
+fn start_game(
+    mut next_state: ResMut<NextState<AppState>>,
+    game: Res<Game>,
+    mut add_event: EventWriter<AddPieceEvent>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+) {
+    if game.cells.iter().any(|cell| cell.is_some()) {
+        restore_event.send(RestoreBoardEvent);
+    } else {
+        add_event.send(AddPieceEvent(2));
+    }
+    next_state.set(AppState::Menu);
+}
+
 #[derive(Component)]
 struct PieceMarker;
 
-#[derive(Event)]
-enum MoveEvent {
-    Up,
-    Down,
-    Right,
-    Left,
-}
-
 #[derive(Component)]
 enum MoveType {
     Move(IVec2),
     MoveDouble((IVec2, Entity)),
 }
 
-#[derive(Clone, Copy)]
-struct Piece {
-    entity: Entity,
-    value: i32,
-    pos: IVec2,
-}
 #[derive(Resource)]
 struct Board {
-    pieces: Vec<Option<Piece>>,
+    pieces: Vec<Option<Entity>>,
 }
+#[derive(Resource, Deref, DerefMut)]
+struct Game(GameState);
 #[derive(Component)]
 struct Value(i32);
 #[derive(Component)]
@@ -600,28 +928,13 @@ enum AppState {
     Anim,
     PostAnim,
     GameOver,
+    Won,
+    Replay,
+    Settings,
+    Menu,
+    Paused,
 }
 
-#[derive(Resource)]
-struct Config {
-    size: i32,
-    tile_size: i32,
-    pad: i32,
-    window_size: Vec2,
-}
-impl Config {
-    fn new() -> Self {
-        Self {
-            size: 4,
-            tile_size: 150,
-            pad: 0,
-            window_size: (900.0, 900.0).into(),
-        }
-    }
-    fn board_size(&self) -> f32 {
-        (self.size * (self.tile_size + 2 * self.pad)) as f32
-    }
-}
 #[derive(Event)]
 struct AddPieceEvent(i32);
 
@@ -638,23 +951,70 @@ struct TitleFont(Handle<Font>);
 #[derive(Resource)]
 struct SpriteHandle(Handle<Aseprite>);
 
-#[derive(Resource)]
-struct ColorMap {
-    map: HashMap<i32, Color>,
-}
-
 #[derive(Resource)]
 struct BoardPivot(Entity);
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct InitSet;
 
+// A move asked for by the player, played now or buffered while the board animates.
+#[derive(Event)]
+struct MoveInputEvent(MoveEvent);
+// A move that would not change the board, rejected instead of played.
+#[derive(Event)]
+struct InvalidMoveEvent(MoveEvent);
 #[derive(Event)]
 struct NewGameEvent;
+#[derive(Event)]
+struct KeepGoingEvent;
+#[derive(Event)]
+struct UndoEvent;
+#[derive(Event)]
+struct RedoEvent;
+#[derive(Event)]
+struct RestoreBoardEvent;
+#[derive(Event)]
+struct ResizeBoardEvent;
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Victory {
+    #[default]
+    NotYet,
+    Reached,
+    KeepGoing,
+}
+
+// The state to go back to when the game is unpaused.
+#[derive(Resource, Default)]
+struct PausedState(AppState);
+
+// Won or GameOver is being entered again for a game that already got there.
+#[derive(Resource, Default)]
+struct Resumed(bool);
 
 #[derive(Resource)]
 struct Score(i32);
 #[derive(Resource)]
 struct ScoreToAdd(i32);
-#[derive(Resource)]
-struct HighScore(i32);
+// The kind of game being played, each with its own high score.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum GameMode {
+    #[default]
+    Classic,
+    Timed,
+    Daily,
+    Puzzle,
+}
+
+#[derive(Resource, Default)]
+struct HighScores(HashMap<GameMode, i32>);
+
+impl HighScores {
+    fn get(&self, mode: GameMode) -> i32 {
+        self.0.get(&mode).copied().unwrap_or(0)
+    }
+    fn update(&mut self, mode: GameMode, score: i32) {
+        let best = self.0.entry(mode).or_insert(0);
+        *best = (*best).max(score);
+    }
+}
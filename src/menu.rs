@@ -0,0 +1,538 @@
+use bevy::{prelude::*, ui::FocusPolicy};
+use bevy_2048::recording::Recording;
+
+use crate::{
+    controls::{Action, Actions, MenuInput},
+    daily::{self, spawn_calendar, DailyHistory, Day},
+    puzzle::{CurrentPuzzle, Levels, StartPuzzleEvent, LEVELS},
+    replay::StartReplayEvent,
+    resume, save,
+    settings::OpenSettingsEvent,
+    theme::Palette,
+    ui::title_text,
+    AppState, Config, Game, GameMode, NewGameEvent, PausedState, PieceFont, Resumed, TitleFont,
+    Victory,
+};
+
+// newest replays shown in the list
+const MAX_REPLAYS: usize = 8;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MainMenu>()
+            .add_event::<OpenMenuEvent>()
+            .add_systems(Update, open_menu)
+            .add_systems(
+                Update,
+                forget_paused
+                    .run_if(in_state(AppState::Menu).and_then(on_event::<NewGameEvent>())),
+            )
+            .add_systems(OnEnter(AppState::Menu), refresh_menu)
+            .add_systems(OnExit(AppState::Menu), remove_menu)
+            .add_systems(
+                Update,
+                (
+                    menu_input,
+                    menu_buttons,
+                    refresh_menu
+                        .run_if(resource_changed::<MainMenu>.or_else(resource_changed::<Palette>)),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Menu)),
+            );
+    }
+}
+
+#[derive(Event)]
+pub struct OpenMenuEvent;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum Screen {
+    #[default]
+    Main,
+    Replays,
+    Daily,
+    Puzzles,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Continue,
+    Classic,
+    Timed,
+    Daily,
+    PlayDaily,
+    Puzzles,
+    Puzzle(usize),
+    Custom,
+    Replays,
+    Settings,
+    Replay(Recording),
+    Back,
+}
+
+struct Item {
+    label: String,
+    entry: Entry,
+    enabled: bool,
+}
+
+// `paused` is where the game was when the menu opened, `None` right after launch.
+// `replays` is read when the replay list opens, not every frame.
+#[derive(Resource, Default)]
+struct MainMenu {
+    screen: Screen,
+    selected: usize,
+    paused: Option<AppState>,
+    replays: Vec<ReplaySummary>,
+}
+
+struct ReplaySummary {
+    recording: Recording,
+    score: i32,
+    max_tile: i32,
+}
+
+#[derive(Component)]
+struct MenuUi;
+
+#[derive(Component)]
+struct MenuButton(usize);
+
+fn open_menu(
+    mut open_event: EventReader<OpenMenuEvent>,
+    actions: Actions,
+    state: Res<State<AppState>>,
+    paused_state: Res<PausedState>,
+    puzzle: Option<Res<CurrentPuzzle>>,
+    mut menu: ResMut<MainMenu>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let open = open_event.read().count() > 0 || actions.just_pressed(Action::Menu);
+    let paused = match state.get() {
+        AppState::Input | AppState::GameOver | AppState::Won => state.get().clone(),
+        AppState::Paused => paused_state.0.clone(),
+        _ => return,
+    };
+    if open {
+        *menu = MainMenu {
+            paused: Some(paused.clone()),
+            ..default()
+        };
+        // a solved puzzle goes on to the next level
+        if let Some(puzzle) = puzzle.filter(|_| paused == AppState::Won) {
+            menu.screen = Screen::Puzzles;
+            menu.selected = LEVELS
+                .iter()
+                .position(|level| *level == puzzle.level)
+                .map_or(0, |i| (i + 1).min(LEVELS.len() - 1));
+        }
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn items(menu: &MainMenu, config: &Config, history: &DailyHistory, levels: &Levels) -> Vec<Item> {
+    let item = |label: &str, entry, enabled| Item {
+        label: label.to_string(),
+        entry,
+        enabled,
+    };
+    match menu.screen {
+        Screen::Main => vec![
+            item("Continue", Entry::Continue, true),
+            item("Classic", Entry::Classic, true),
+            item(
+                &format!("Timed ({})", minutes(config.timed_secs)),
+                Entry::Timed,
+                true,
+            ),
+            item("Daily", Entry::Daily, true),
+            item("Puzzles", Entry::Puzzles, true),
+            item("Custom board", Entry::Custom, true),
+            item("Replays", Entry::Replays, true),
+            item("Settings", Entry::Settings, true),
+        ],
+        Screen::Replays => {
+            let mut items: Vec<Item> = menu
+                .replays
+                .iter()
+                .map(|replay| Item {
+                    label: format!(
+                        "{0}x{0}  {1} moves  score {2}  max {3}",
+                        replay.recording.size,
+                        replay.recording.moves.len(),
+                        replay.score,
+                        replay.max_tile
+                    ),
+                    enabled: replay.recording.size == config.size,
+                    entry: Entry::Replay(replay.recording.clone()),
+                })
+                .collect();
+            if items.is_empty() {
+                items.push(item("no replays yet", Entry::Back, false));
+            }
+            items.push(item("Back", Entry::Back, true));
+            items
+        }
+        Screen::Daily => {
+            let play = if config.size != daily::SIZE {
+                item("Daily boards are 4x4", Entry::PlayDaily, false)
+            } else if history.get(Day::today()).is_some() {
+                item("Practice today's board", Entry::PlayDaily, true)
+            } else {
+                item("Play today's board", Entry::PlayDaily, true)
+            };
+            vec![play, item("Back", Entry::Back, true)]
+        }
+        Screen::Puzzles => {
+            let mut items: Vec<Item> = LEVELS
+                .iter()
+                .enumerate()
+                .map(|(i, level)| match levels.get(i) {
+                    Some(puzzle) => Item {
+                        label: format!(
+                            "{}. {}  {} in {}  {}",
+                            i + 1,
+                            puzzle.name,
+                            puzzle.goal.label(),
+                            puzzle.moves,
+                            match levels.progress.best(level) {
+                                Some(best) => format!("solved in {best}"),
+                                None => "unsolved".to_string(),
+                            }
+                        ),
+                        entry: Entry::Puzzle(i),
+                        enabled: puzzle.size == config.size,
+                    },
+                    None => item("loading", Entry::Back, false),
+                })
+                .collect();
+            items.push(item("Back", Entry::Back, true));
+            items
+        }
+    }
+}
+
+fn minutes(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+// Replay files are named `{seed}-{started}.txt`, newest first.
+fn replays() -> Vec<ReplaySummary> {
+    let mut names: Vec<(u64, String)> = save::list("replays")
+        .into_iter()
+        .filter_map(|name| {
+            let started = name
+                .strip_suffix(".txt")?
+                .rsplit_once('-')?
+                .1
+                .parse()
+                .ok()?;
+            Some((started, name))
+        })
+        .collect();
+    names.sort_by(|a, b| b.cmp(a));
+    names
+        .into_iter()
+        .filter_map(|(_, name)| save::read(&format!("replays/{name}")))
+        .filter_map(|text| Recording::from_text(&text).ok())
+        .take(MAX_REPLAYS)
+        .map(|recording| {
+            let end = recording.state_at(recording.moves.len());
+            ReplaySummary {
+                score: end.score,
+                max_tile: end.max_tile(),
+                recording,
+            }
+        })
+        .collect()
+}
+
+// The game the menu was opened over is gone, Continue picks up the new one.
+fn forget_paused(mut menu: ResMut<MainMenu>) {
+    menu.paused = None;
+}
+
+fn select(
+    item: &Item,
+    menu: &mut MainMenu,
+    game: &Game,
+    victory: &Victory,
+    mode: &mut GameMode,
+    next_state: &mut NextState<AppState>,
+    resumed: &mut Resumed,
+    new_game: &mut EventWriter<NewGameEvent>,
+    settings: &mut EventWriter<OpenSettingsEvent>,
+    replay: &mut EventWriter<StartReplayEvent>,
+    puzzle: &mut EventWriter<StartPuzzleEvent>,
+) {
+    if !item.enabled {
+        return;
+    }
+    match &item.entry {
+        Entry::Continue => {
+            let state = match &menu.paused {
+                Some(state) => state.clone(),
+                // a game saved on the win screen opens on it again
+                None if *victory == Victory::Reached => AppState::Won,
+                None if !game.can_move() => AppState::GameOver,
+                None => AppState::Input,
+            };
+            resume(&state, resumed, next_state);
+        }
+        Entry::Classic => {
+            *mode = GameMode::Classic;
+            new_game.send(NewGameEvent);
+            next_state.set(AppState::Input);
+        }
+        Entry::Timed => {
+            *mode = GameMode::Timed;
+            new_game.send(NewGameEvent);
+            next_state.set(AppState::Input);
+        }
+        Entry::Custom => {
+            *mode = GameMode::Classic;
+            settings.send(OpenSettingsEvent { new_game: true });
+        }
+        Entry::Settings => {
+            settings.send(OpenSettingsEvent::default());
+        }
+        Entry::Replays => {
+            menu.screen = Screen::Replays;
+            menu.selected = 0;
+            menu.replays = replays();
+        }
+        Entry::Daily => {
+            menu.screen = Screen::Daily;
+            menu.selected = 0;
+        }
+        Entry::PlayDaily => {
+            *mode = GameMode::Daily;
+            new_game.send(NewGameEvent);
+            next_state.set(AppState::Input);
+        }
+        Entry::Puzzles => {
+            menu.screen = Screen::Puzzles;
+            menu.selected = 0;
+        }
+        Entry::Puzzle(level) => {
+            puzzle.send(StartPuzzleEvent(*level));
+        }
+        Entry::Replay(recording) => {
+            replay.send(StartReplayEvent {
+                recording: recording.clone(),
+                resume: true,
+            });
+        }
+        Entry::Back => {
+            menu.screen = Screen::Main;
+            menu.selected = 0;
+        }
+    }
+}
+
+fn menu_input(
+    actions: Actions,
+    menu_input: MenuInput,
+    mut menu: ResMut<MainMenu>,
+    config: Res<Config>,
+    history: Res<DailyHistory>,
+    game: Res<Game>,
+    victory: Res<Victory>,
+    mut mode: ResMut<GameMode>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resumed: ResMut<Resumed>,
+    mut new_game: EventWriter<NewGameEvent>,
+    mut settings: EventWriter<OpenSettingsEvent>,
+    mut replay: EventWriter<StartReplayEvent>,
+    mut puzzle: EventWriter<StartPuzzleEvent>,
+    levels: Levels,
+) {
+    let items = items(&menu, &config, &history, &levels);
+    if actions.just_pressed(Action::Up) {
+        menu.selected = (menu.selected + items.len() - 1) % items.len();
+    }
+    if actions.just_pressed(Action::Down) {
+        menu.selected = (menu.selected + 1) % items.len();
+    }
+    if menu_input.confirm() {
+        let item = &items[menu.selected.min(items.len() - 1)];
+        select(
+            item,
+            &mut menu,
+            &game,
+            &victory,
+            &mut mode,
+            &mut next_state,
+            &mut resumed,
+            &mut new_game,
+            &mut settings,
+            &mut replay,
+            &mut puzzle,
+        );
+    } else if menu_input.back() {
+        let back = match menu.screen {
+            Screen::Main => Entry::Continue,
+            Screen::Replays | Screen::Daily | Screen::Puzzles => Entry::Back,
+        };
+        let item = Item {
+            label: String::new(),
+            entry: back,
+            enabled: true,
+        };
+        select(
+            &item,
+            &mut menu,
+            &game,
+            &victory,
+            &mut mode,
+            &mut next_state,
+            &mut resumed,
+            &mut new_game,
+            &mut settings,
+            &mut replay,
+            &mut puzzle,
+        );
+    }
+}
+
+fn menu_buttons(
+    interactions: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut menu: ResMut<MainMenu>,
+    config: Res<Config>,
+    history: Res<DailyHistory>,
+    game: Res<Game>,
+    victory: Res<Victory>,
+    mut mode: ResMut<GameMode>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resumed: ResMut<Resumed>,
+    mut new_game: EventWriter<NewGameEvent>,
+    mut settings: EventWriter<OpenSettingsEvent>,
+    mut replay: EventWriter<StartReplayEvent>,
+    mut puzzle: EventWriter<StartPuzzleEvent>,
+    levels: Levels,
+) {
+    for (interaction, button) in interactions.iter() {
+        match interaction {
+            // any write rebuilds the menu, and the rebuilt button is hovered again
+            Interaction::Hovered if menu.selected != button.0 => menu.selected = button.0,
+            Interaction::Hovered => {}
+            Interaction::Pressed => {
+                let items = items(&menu, &config, &history, &levels);
+                if let Some(item) = items.get(button.0) {
+                    select(
+                        item,
+                        &mut menu,
+                        &game,
+                        &victory,
+                        &mut mode,
+                        &mut next_state,
+                        &mut resumed,
+                        &mut new_game,
+                        &mut settings,
+                        &mut replay,
+                        &mut puzzle,
+                    );
+                }
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+// Rebuilt whenever the screen or the selection changes.
+fn refresh_menu(
+    mut commands: Commands,
+    menu: Res<MainMenu>,
+    config: Res<Config>,
+    history: Res<DailyHistory>,
+    levels: Levels,
+    font: Res<PieceFont>,
+    title_font: Res<TitleFont>,
+    palette: Res<Palette>,
+    query: Query<Entity, With<MenuUi>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let items = items(&menu, &config, &history, &levels);
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: palette.background.into(),
+                // keeps clicks off the board buttons underneath
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            MenuUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: title_text(&title_font, &palette),
+                style: Style {
+                    margin: UiRect::bottom(Val::Px(40.0)),
+                    ..default()
+                },
+                ..default()
+            });
+            if menu.screen == Screen::Daily {
+                spawn_calendar(parent, &history, &font, &palette);
+            }
+            for (i, item) in items.iter().enumerate() {
+                let color = if !item.enabled {
+                    palette.button
+                } else if i == menu.selected {
+                    palette.accent
+                } else {
+                    palette.text
+                };
+                let font_size = match menu.screen {
+                    Screen::Main => 50.0,
+                    Screen::Replays | Screen::Puzzles => 30.0,
+                    Screen::Daily => 40.0,
+                };
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                height: Val::Px(font_size + 10.0),
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::NONE.into(),
+                            ..default()
+                        },
+                        MenuButton(i),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            item.label.clone(),
+                            TextStyle {
+                                font: font.0.clone_weak(),
+                                font_size,
+                                color,
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn remove_menu(mut commands: Commands, query: Query<Entity, With<MenuUi>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
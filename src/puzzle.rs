@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    ecs::system::SystemParam,
+    prelude::*,
+};
+use bevy_2048::game::GameState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check_game_end, new_game_event, replay::GameRecord, replay::ReplayPlayer, save, AddPieceEvent,
+    AppState, Config, Game, GameMode, NewGameEvent,
+};
+
+// the levels in assets/puzzles, in the order the level select lists them
+pub const LEVELS: [&str; 5] = ["first-steps", "corner", "tidy-up", "score-rush", "long-way"];
+const PROGRESS_FILE: &str = "puzzles.ron";
+
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Puzzle>()
+            .init_asset_loader::<PuzzleLoader>()
+            .init_resource::<LevelHandles>()
+            .insert_resource(PuzzleProgress::load())
+            .add_event::<StartPuzzleEvent>()
+            .add_systems(
+                Update,
+                (start_puzzle, apply_deferred)
+                    .chain()
+                    .before(new_game_event)
+                    .run_if(on_event::<StartPuzzleEvent>()),
+            )
+            .add_systems(
+                Update,
+                leave_puzzle
+                    .after(new_game_event)
+                    .run_if(on_event::<NewGameEvent>()),
+            )
+            .add_systems(
+                Update,
+                check_puzzle
+                    .after(check_game_end)
+                    .run_if(on_event::<AddPieceEvent>())
+                    .run_if(resource_exists::<CurrentPuzzle>)
+                    .run_if(not(resource_exists::<ReplayPlayer>)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Goal {
+    // make a tile of this value
+    Tile(i32),
+    // leave at most this many tiles on the board
+    Tiles(usize),
+    Score(i32),
+}
+
+impl Goal {
+    fn reached(self, game: &GameState) -> bool {
+        match self {
+            Goal::Tile(value) => game.max_tile() >= value,
+            Goal::Tiles(count) => game.cells.iter().flatten().count() <= count,
+            Goal::Score(score) => game.score >= score,
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            Goal::Tile(value) => format!("make a {value}"),
+            Goal::Tiles(1) => "leave one tile".to_string(),
+            Goal::Tiles(count) => format!("leave {count} tiles"),
+            Goal::Score(score) => format!("score {score}"),
+        }
+    }
+}
+
+#[derive(Asset, TypePath, Clone, Debug, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    pub size: i32,
+    cells: Vec<Option<i32>>,
+    spawns: VecDeque<(IVec2, i32)>,
+    // most moves allowed
+    pub moves: usize,
+    pub goal: Goal,
+}
+
+impl Puzzle {
+    pub fn start(&self) -> GameState {
+        GameState {
+            cells: self.cells.clone(),
+            script: Some(self.spawns.clone()),
+            ..GameState::new(self.size, 0)
+        }
+    }
+}
+
+// The file format. Rows of the board are written as text, "." for an empty cell, and
+// spawns as (x, y, value), counted from the top left. One spawn comes after each move; a
+// taken cell means the first empty one, and nothing spawns once the list runs out.
+#[derive(Deserialize)]
+struct PuzzleFile {
+    name: String,
+    board: Vec<String>,
+    #[serde(default)]
+    spawns: Vec<(i32, i32, i32)>,
+    moves: usize,
+    goal: Goal,
+}
+
+fn check_tile(value: i32) -> Result<i32, String> {
+    if value >= 2 && value.count_ones() == 1 {
+        Ok(value)
+    } else {
+        Err(format!("invalid tile {value}, tiles are powers of two"))
+    }
+}
+
+impl PuzzleFile {
+    fn puzzle(self) -> Result<Puzzle, String> {
+        let size = self.board.len() as i32;
+        if !(3..=8).contains(&size) {
+            return Err(format!("boards are 3 to 8 rows, got {size}"));
+        }
+        let mut cells = vec![];
+        for row in &self.board {
+            let row: Vec<&str> = row.split_whitespace().collect();
+            if row.len() as i32 != size {
+                return Err(format!("every row needs {size} cells"));
+            }
+            for cell in row {
+                cells.push(match cell {
+                    "." => None,
+                    value => Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid cell {value:?}"))
+                            .and_then(check_tile)?,
+                    ),
+                });
+            }
+        }
+        let spawns = self
+            .spawns
+            .iter()
+            .map(|&(x, y, value)| {
+                if !(0..size).contains(&x) || !(0..size).contains(&y) {
+                    return Err(format!("spawn ({x}, {y}) is off the board"));
+                }
+                Ok((IVec2::new(x, y), check_tile(value)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Puzzle {
+            name: self.name,
+            size,
+            cells,
+            spawns,
+            moves: self.moves,
+            goal: self.goal,
+        })
+    }
+}
+
+#[derive(Default)]
+struct PuzzleLoader;
+
+impl AssetLoader for PuzzleLoader {
+    type Asset = Puzzle;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Puzzle, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: PuzzleFile = ron::de::from_bytes(&bytes)?;
+        Ok(file.puzzle()?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["puzzle.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct LevelHandles(Vec<Handle<Puzzle>>);
+
+impl FromWorld for LevelHandles {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        LevelHandles(
+            LEVELS
+                .iter()
+                .map(|level| asset_server.load(format!("puzzles/{level}.puzzle.ron")))
+                .collect(),
+        )
+    }
+}
+
+// Fewest moves each solved level took, by file name.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct PuzzleProgress {
+    best: BTreeMap<String, usize>,
+}
+
+impl PuzzleProgress {
+    fn load() -> Self {
+        save::read(PROGRESS_FILE)
+            .and_then(|text| ron::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match ron::to_string(self) {
+            Ok(text) => save::write(PROGRESS_FILE, &text),
+            Err(err) => warn!("could not serialize puzzle progress: {err}"),
+        }
+    }
+
+    pub fn best(&self, level: &str) -> Option<usize> {
+        self.best.get(level).copied()
+    }
+}
+
+// The levels as far as they have loaded, with the player's progress.
+#[derive(SystemParam)]
+pub struct Levels<'w> {
+    handles: Res<'w, LevelHandles>,
+    puzzles: Res<'w, Assets<Puzzle>>,
+    pub progress: Res<'w, PuzzleProgress>,
+}
+
+impl Levels<'_> {
+    pub fn get(&self, level: usize) -> Option<&Puzzle> {
+        self.puzzles.get(self.handles.0.get(level)?)
+    }
+}
+
+// The level being played, kept whole so restarting doesn't depend on the asset.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct CurrentPuzzle {
+    pub level: String,
+    pub puzzle: Puzzle,
+}
+
+#[derive(Event)]
+pub struct StartPuzzleEvent(pub usize);
+
+fn start_puzzle(
+    mut commands: Commands,
+    mut start_event: EventReader<StartPuzzleEvent>,
+    levels: Levels,
+    mut mode: ResMut<GameMode>,
+    mut new_game: EventWriter<NewGameEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(StartPuzzleEvent(level)) = start_event.read().last() else {
+        return;
+    };
+    if let Some(puzzle) = levels.get(*level) {
+        commands.insert_resource(CurrentPuzzle {
+            level: LEVELS[*level].to_string(),
+            puzzle: puzzle.clone(),
+        });
+        *mode = GameMode::Puzzle;
+        new_game.send(NewGameEvent);
+        next_state.set(AppState::Input);
+    }
+}
+
+// A board resized from the settings can't hold the level any more, it becomes classic.
+fn leave_puzzle(
+    mut commands: Commands,
+    mut mode: ResMut<GameMode>,
+    current: Option<Res<CurrentPuzzle>>,
+    config: Res<Config>,
+) {
+    let fits = current.is_some_and(|current| current.puzzle.size == config.size);
+    if *mode == GameMode::Puzzle && !fits {
+        *mode = GameMode::Classic;
+    }
+    if *mode != GameMode::Puzzle {
+        commands.remove_resource::<CurrentPuzzle>();
+    }
+}
+
+// Checked once the tile after each move is down.
+fn check_puzzle(
+    current: Res<CurrentPuzzle>,
+    game: Res<Game>,
+    record: Res<GameRecord>,
+    mut progress: ResMut<PuzzleProgress>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let moves = record.recording.moves.len();
+    if current.puzzle.goal.reached(&game) {
+        let best = progress.best.entry(current.level.clone()).or_insert(moves);
+        *best = (*best).min(moves);
+        progress.save();
+        next_state.set(AppState::Won);
+    } else if moves >= current.puzzle.moves {
+        next_state.set(AppState::GameOver);
+    }
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::{GameState, MoveEvent};
+
+const HEADER: &str = "2048-replay 1";
+
+// Everything needed to play a game again: the rules it started with and every move made.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub size: i32,
+    pub seed: u64,
+    pub four_chance: f32,
+    pub moves: Vec<MoveEvent>,
+}
+
+impl Recording {
+    // `game` has to be a fresh game, before its first tiles are spawned.
+    pub fn new(game: &GameState) -> Self {
+        Self {
+            size: game.size,
+            seed: game.rng.seed,
+            four_chance: game.four_chance,
+            moves: vec![],
+        }
+    }
+
+    pub fn start(&self) -> GameState {
+        let mut game = GameState {
+            four_chance: self.four_chance,
+            ..GameState::new(self.size, self.seed)
+        };
+        game.spawn();
+        game.spawn();
+        game
+    }
+
+    // The game as it was after the first `moves` moves.
+    pub fn state_at(&self, moves: usize) -> GameState {
+        let mut game = self.start();
+        for event in &self.moves[..moves.min(self.moves.len())] {
+            game.play(*event);
+        }
+        game
+    }
+
+    pub fn to_text(&self) -> String {
+        let moves: String = self.moves.iter().map(|event| move_char(*event)).collect();
+        format!(
+            "{HEADER}\nsize {}\nseed {}\nfour {}\nmoves {moves}\n",
+            self.size, self.seed, self.four_chance
+        )
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err("not a 2048 replay".to_string());
+        }
+        let mut recording = Recording {
+            size: 4,
+            seed: 0,
+            four_chance: 0.1,
+            moves: vec![],
+        };
+        for line in lines {
+            let (key, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let invalid = || format!("invalid replay line: {line}");
+            match key {
+                "size" => recording.size = value.parse().map_err(|_| invalid())?,
+                "seed" => recording.seed = value.parse().map_err(|_| invalid())?,
+                "four" => recording.four_chance = value.parse().map_err(|_| invalid())?,
+                "moves" => {
+                    recording.moves = value
+                        .chars()
+                        .filter(|c| !c.is_whitespace())
+                        .map(|c| char_move(c).ok_or_else(invalid))
+                        .collect::<Result<_, _>>()?
+                }
+                "" => {}
+                _ => return Err(invalid()),
+            }
+        }
+        if !(3..=8).contains(&recording.size) {
+            return Err(format!(
+                "replay board size must be between 3 and 8, got {}",
+                recording.size
+            ));
+        }
+        if !(0.0..=1.0).contains(&recording.four_chance) {
+            return Err("replay four chance must be between 0 and 1".to_string());
+        }
+        Ok(recording)
+    }
+}
+
+fn move_char(event: MoveEvent) -> char {
+    match event {
+        MoveEvent::Up => 'U',
+        MoveEvent::Down => 'D',
+        MoveEvent::Right => 'R',
+        MoveEvent::Left => 'L',
+    }
+}
+
+fn char_move(c: char) -> Option<MoveEvent> {
+    match c {
+        'U' => Some(MoveEvent::Up),
+        'D' => Some(MoveEvent::Down),
+        'R' => Some(MoveEvent::Right),
+        'L' => Some(MoveEvent::Left),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let recording = Recording {
+            size: 5,
+            seed: 20240131,
+            four_chance: 0.25,
+            moves: MoveEvent::ALL.to_vec(),
+        };
+        let parsed = Recording::from_text(&recording.to_text()).unwrap();
+        assert_eq!(parsed.size, recording.size);
+        assert_eq!(parsed.seed, recording.seed);
+        assert_eq!(parsed.four_chance, recording.four_chance);
+        assert_eq!(parsed.moves, recording.moves);
+    }
+
+    #[test]
+    fn rejects_bad_replays() {
+        let replay = |body: &str| Recording::from_text(&format!("{HEADER}\n{body}"));
+        assert!(Recording::from_text("size 4\nmoves UL\n").is_err());
+        assert!(replay("moves UX\n").is_err());
+        assert!(replay("speed 2\n").is_err());
+        assert!(replay("size 2\n").is_err());
+        assert!(replay("size 9\n").is_err());
+        assert!(replay("four 1.5\n").is_err());
+        assert!(replay("four -0.1\n").is_err());
+        assert!(replay("four NaN\n").is_err());
+        assert!(replay("size 8\nfour 1\nmoves U D\n").is_ok());
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::{prelude::*, utils::SystemTime};
+use bevy_2048::{
+    game::{GameState, MoveEvent},
+    recording::Recording,
+};
+
+use crate::{
+    add_piece_event, new_game_event, not_resumed, restore_board_event, save, start_game,
+    theme::{Ink, Palette, Themed},
+    AppState, Config, Game, InitSet, NewGameEvent, PieceFont, RestoreBoardEvent,
+};
+
+// time between two replayed moves at speed 1
+const MOVE_INTERVAL: f32 = 0.4;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRecord>()
+            .add_event::<StartReplayEvent>()
+            .add_systems(
+                OnEnter(AppState::Setup),
+                (
+                    sync_record.after(save::load_game).before(start_game),
+                    replay_from_cli.after(start_game),
+                )
+                    .in_set(InitSet),
+            )
+            .add_systems(
+                Update,
+                record_move
+                    .run_if(on_event::<MoveEvent>())
+                    .run_if(not(resource_exists::<ReplayPlayer>)),
+            )
+            .add_systems(
+                Update,
+                reset_record
+                    .after(new_game_event)
+                    .run_if(on_event::<NewGameEvent>()),
+            )
+            .add_systems(
+                Update,
+                stop_replay
+                    .run_if(on_event::<NewGameEvent>())
+                    .run_if(resource_exists::<ReplayPlayer>),
+            )
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                store_record
+                    .run_if(not(resource_exists::<ReplayPlayer>))
+                    .run_if(not_resumed),
+            )
+            .add_systems(
+                Update,
+                start_replay
+                    .after(add_piece_event)
+                    .before(restore_board_event)
+                    .run_if(on_event::<StartReplayEvent>()),
+            )
+            .add_systems(
+                Update,
+                (
+                    replay_controls,
+                    replay_tick.run_if(not(on_event::<RestoreBoardEvent>())),
+                )
+                    .chain()
+                    .before(restore_board_event)
+                    .run_if(in_state(AppState::Replay)),
+            )
+            .add_systems(
+                Update,
+                update_replay_ui.run_if(resource_exists_and_changed::<ReplayPlayer>),
+            );
+    }
+}
+
+// The moves of the game being played, kept so it can be replayed and shared.
+#[derive(Resource)]
+pub struct GameRecord {
+    pub recording: Recording,
+    started: u64,
+    // puzzles start from a layout instead of a seed, their moves can't be played back
+    pub replayable: bool,
+}
+
+impl Default for GameRecord {
+    fn default() -> Self {
+        Self {
+            recording: Recording::new(&GameState::new(0, 0)),
+            started: now(),
+            replayable: true,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    recording: Recording,
+    next: usize,
+    paused: bool,
+    speed: f32,
+    timer: Timer,
+    // the live game to go back to once the replay is closed
+    resume: Option<GameState>,
+}
+
+#[derive(Event)]
+pub struct StartReplayEvent {
+    pub recording: Recording,
+    pub resume: bool,
+}
+
+#[derive(Component)]
+struct ReplayUi;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+fn sync_record(game: Res<Game>, mut record: ResMut<GameRecord>) {
+    if record.recording.seed != game.rng.seed || record.recording.size != game.size {
+        *record = GameRecord {
+            recording: Recording::new(&game),
+            started: now(),
+            replayable: true,
+        };
+    }
+    record.replayable = game.script.is_none();
+}
+
+fn record_move(mut move_event: EventReader<MoveEvent>, mut record: ResMut<GameRecord>) {
+    for event in move_event.read() {
+        record.recording.moves.push(*event);
+    }
+}
+
+fn store_record(record: Res<GameRecord>) {
+    write_record(&record);
+}
+
+fn write_record(record: &GameRecord) {
+    if !record.recording.moves.is_empty() && record.replayable {
+        let name = format!("replays/{}-{}.txt", record.recording.seed, record.started);
+        save::write(&name, &record.recording.to_text());
+    }
+}
+
+fn reset_record(game: Res<Game>, mut record: ResMut<GameRecord>) {
+    // games that ended were already stored on game over, this keeps abandoned ones too
+    write_record(&record);
+    *record = GameRecord {
+        recording: Recording::new(&game),
+        started: now(),
+        replayable: game.script.is_none(),
+    };
+}
+
+fn replay_from_cli(config: Res<Config>, mut start_event: EventWriter<StartReplayEvent>) {
+    let Some(path) = &config.replay else {
+        return;
+    };
+    let recording = std::fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| Recording::from_text(&text));
+    match recording {
+        Ok(recording) => {
+            start_event.send(StartReplayEvent {
+                recording,
+                resume: false,
+            });
+        }
+        Err(err) => warn!("could not load replay {}: {err}", path.display()),
+    }
+}
+
+fn start_replay(
+    mut commands: Commands,
+    mut start_event: EventReader<StartReplayEvent>,
+    mut game: ResMut<Game>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    ui_query: Query<Entity, With<ReplayUi>>,
+    font: Res<PieceFont>,
+    palette: Res<Palette>,
+    config: Res<Config>,
+) {
+    let mut started = false;
+    for event in start_event.read() {
+        // the board on screen is laid out for `config.size`
+        if event.recording.size != config.size {
+            warn!(
+                "can't play a {0}x{0} replay on a {1}x{1} board, start with --size {0}",
+                event.recording.size, config.size
+            );
+            continue;
+        }
+        let resume = event.resume.then(|| game.0.clone());
+        game.0 = event.recording.state_at(0);
+        commands.insert_resource(ReplayPlayer {
+            recording: event.recording.clone(),
+            next: 0,
+            paused: false,
+            speed: 1.0,
+            timer: Timer::from_seconds(MOVE_INTERVAL, TimerMode::Repeating),
+            resume,
+        });
+        restore_event.send(RestoreBoardEvent);
+        next_state.set(AppState::Replay);
+        started = true;
+    }
+    if started && ui_query.is_empty() {
+        spawn_replay_ui(&mut commands, &font, &palette);
+    }
+}
+
+fn replay_tick(
+    time: Res<Time>,
+    mut player: ResMut<ReplayPlayer>,
+    mut move_event: EventWriter<MoveEvent>,
+) {
+    if player.paused || player.next >= player.recording.moves.len() {
+        return;
+    }
+    let delta = time.delta().mul_f32(player.speed);
+    if player.timer.tick(delta).just_finished() {
+        let next = player.next;
+        move_event.send(player.recording.moves[next]);
+        player.next += 1;
+    }
+}
+
+fn replay_controls(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut player: ResMut<ReplayPlayer>,
+    mut game: ResMut<Game>,
+    mut move_event: EventWriter<MoveEvent>,
+    mut restore_event: EventWriter<RestoreBoardEvent>,
+    mut new_game_event: EventWriter<NewGameEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    ui_query: Query<Entity, With<ReplayUi>>,
+) {
+    let len = player.recording.moves.len();
+    let mut scrub_to = None;
+    if keys.just_pressed(KeyCode::Space) {
+        player.paused = !player.paused;
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        player.paused = true;
+        if player.next < len {
+            let next = player.next;
+            move_event.send(player.recording.moves[next]);
+            player.next += 1;
+        }
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        player.paused = true;
+        scrub_to = Some(player.next.saturating_sub(1));
+    } else if keys.just_pressed(KeyCode::BracketLeft) {
+        scrub_to = Some(player.next.saturating_sub(10));
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        scrub_to = Some((player.next + 10).min(len));
+    } else if keys.just_pressed(KeyCode::Home) {
+        scrub_to = Some(0);
+    } else if keys.just_pressed(KeyCode::End) {
+        scrub_to = Some(len);
+    } else if keys.just_pressed(KeyCode::ArrowUp) {
+        player.speed = (player.speed * 2.0).min(16.0);
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        player.speed = (player.speed / 2.0).max(0.25);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        match player.resume.take() {
+            Some(resume) => {
+                game.0 = resume;
+                restore_event.send(RestoreBoardEvent);
+                next_state.set(AppState::Input);
+                close_replay(&mut commands, &ui_query);
+            }
+            // stop_replay cleans up once the new game starts
+            None => {
+                new_game_event.send(NewGameEvent);
+            }
+        }
+    }
+    if let Some(moves) = scrub_to {
+        game.0 = player.recording.state_at(moves);
+        player.next = moves;
+        player.timer.reset();
+        restore_event.send(RestoreBoardEvent);
+    }
+}
+
+fn stop_replay(mut commands: Commands, ui_query: Query<Entity, With<ReplayUi>>) {
+    close_replay(&mut commands, &ui_query);
+}
+
+fn close_replay(commands: &mut Commands, ui_query: &Query<Entity, With<ReplayUi>>) {
+    commands.remove_resource::<ReplayPlayer>();
+    for entity in ui_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_replay_ui(commands: &mut Commands, font: &PieceFont, palette: &Palette) {
+    let style = |font_size, color| TextStyle {
+        font: font.0.clone_weak(),
+        font_size,
+        color,
+    };
+    commands.spawn((
+        TextBundle {
+            text: Text::from_sections([
+                TextSection::new("", style(30.0, palette.text)),
+                TextSection::new(
+                    "\nSpace pause  <- -> step  [ ] skip 10  Up/Down speed  Esc exit",
+                    style(20.0, palette.text_dim),
+                ),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                // below the seed and speed lines
+                left: Val::Px(20.0),
+                top: Val::Px(90.0),
+                ..default()
+            },
+            ..default()
+        },
+        ReplayUi,
+        Themed(vec![Ink::Text, Ink::Dim]),
+    ));
+}
+
+fn update_replay_ui(player: Res<ReplayPlayer>, mut query: Query<&mut Text, With<ReplayUi>>) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Replay {}/{}  x{}{}",
+            player.next,
+            player.recording.moves.len(),
+            player.speed,
+            if player.paused { "  paused" } else { "" }
+        );
+    }
+}
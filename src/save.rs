@@ -0,0 +1,208 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use bevy_2048::{game::GameState, recording::Recording};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    add_piece_event, create_board, daily::DailyGame, puzzle::CurrentPuzzle, replay::GameRecord,
+    replay::ReplayPlayer, restore_board_event, start_game, timed::Countdown, AddPieceEvent,
+    AppState, Config, Game, GameMode, HighScores, InitSet, RestoreBoardEvent, Victory,
+};
+
+const SAVE_FILE: &str = "save.ron";
+// where a save that can't be read is moved, so the next save doesn't overwrite it
+const UNREADABLE_SAVE_FILE: &str = "save.unreadable.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(AppState::Setup),
+            load_game
+                .in_set(InitSet)
+                .after(create_board)
+                .before(start_game),
+        )
+        .add_systems(
+            Update,
+            save_game
+                .after(add_piece_event)
+                .after(restore_board_event)
+                .run_if(on_event::<AddPieceEvent>().or_else(on_event::<RestoreBoardEvent>()))
+                .run_if(not(resource_exists::<ReplayPlayer>)),
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+    // the classic high score, kept as it was from before there were other modes
+    high_score: i32,
+    #[serde(default)]
+    high_scores: HashMap<GameMode, i32>,
+    game: GameState,
+    // all that saves from before `victory` knew
+    #[serde(default)]
+    keep_going: bool,
+    #[serde(default)]
+    recording: Option<Recording>,
+    #[serde(default)]
+    mode: GameMode,
+    #[serde(default)]
+    time_left: Option<Duration>,
+    #[serde(default)]
+    daily: Option<DailyGame>,
+    #[serde(default)]
+    puzzle: Option<CurrentPuzzle>,
+    #[serde(default)]
+    victory: Option<Victory>,
+}
+
+pub fn load_game(
+    mut commands: Commands,
+    mut game: ResMut<Game>,
+    mut high_scores: ResMut<HighScores>,
+    mut mode: ResMut<GameMode>,
+    mut victory: ResMut<Victory>,
+    mut record: ResMut<GameRecord>,
+    config: Res<Config>,
+) {
+    let Some(text) = read(SAVE_FILE) else {
+        return;
+    };
+    let data = match ron::from_str::<SaveData>(&text) {
+        Ok(data) => data,
+        Err(err) => {
+            warn!("could not read {SAVE_FILE}, keeping it as {UNREADABLE_SAVE_FILE}: {err}");
+            write(UNREADABLE_SAVE_FILE, &text);
+            return;
+        }
+    };
+    high_scores.0 = data.high_scores;
+    high_scores.update(GameMode::Classic, data.high_score);
+    if data.game.size == config.size {
+        game.0 = data.game;
+        *mode = data.mode;
+        if let Some(time_left) = data.time_left {
+            commands.insert_resource(Countdown(time_left));
+        }
+        if let Some(daily) = data.daily {
+            commands.insert_resource(daily);
+        }
+        if let Some(puzzle) = data.puzzle {
+            commands.insert_resource(puzzle);
+        }
+        *victory = match data.victory {
+            Some(victory) => victory,
+            None if data.keep_going => Victory::KeepGoing,
+            None => Victory::NotYet,
+        };
+        if let Some(recording) = data.recording {
+            record.recording = recording;
+        }
+    }
+}
+
+fn save_game(
+    game: Res<Game>,
+    high_scores: Res<HighScores>,
+    mode: Res<GameMode>,
+    victory: Res<Victory>,
+    record: Res<GameRecord>,
+    countdown: Option<Res<Countdown>>,
+    daily: Option<Res<DailyGame>>,
+    puzzle: Option<Res<CurrentPuzzle>>,
+) {
+    let mut high_scores = HighScores(high_scores.0.clone());
+    high_scores.update(*mode, game.score);
+    let data = SaveData {
+        high_score: high_scores.get(GameMode::Classic),
+        high_scores: high_scores.0,
+        game: game.0.clone(),
+        keep_going: *victory == Victory::KeepGoing,
+        recording: Some(record.recording.clone()),
+        mode: *mode,
+        time_left: countdown.map(|countdown| countdown.0),
+        daily: daily.map(|daily| *daily),
+        puzzle: puzzle.map(|puzzle| puzzle.clone()),
+        victory: Some(*victory),
+    };
+    match ron::to_string(&data) {
+        Ok(text) => write(SAVE_FILE, &text),
+        Err(err) => warn!("could not serialize save: {err}"),
+    }
+}
+
+// Files live under the platform data dir on native and in local storage on the web,
+// keyed by the same relative name.
+#[cfg(not(target_arch = "wasm32"))]
+fn data_path(name: &str) -> Option<std::path::PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("bevy-2048").join(name))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(name: &str) -> Option<String> {
+    std::fs::read_to_string(data_path(name)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(name: &str, text: &str) {
+    let Some(path) = data_path(name) else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(err) = std::fs::write(&path, text) {
+        warn!("could not write {}: {err}", path.display());
+    }
+}
+
+// Names of the files in `dir`, without the directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list(dir: &str) -> Vec<String> {
+    let Some(entries) = data_path(dir).and_then(|path| std::fs::read_dir(path).ok()) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(name: &str) -> Option<String> {
+    storage()?.get_item(&format!("bevy-2048/{name}")).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(name: &str, text: &str) {
+    if let Some(storage) = storage() {
+        if storage
+            .set_item(&format!("bevy-2048/{name}"), text)
+            .is_err()
+        {
+            warn!("could not write {name} to local storage");
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn list(dir: &str) -> Vec<String> {
+    let Some(storage) = storage() else {
+        return vec![];
+    };
+    let prefix = format!("bevy-2048/{dir}/");
+    let len = storage.length().unwrap_or(0);
+    (0..len)
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+        .collect()
+}
@@ -0,0 +1,452 @@
+use bevy::prelude::*;
+
+use crate::{
+    config::{AnimationSpeed, Settings},
+    controls::{rebind_input, Action, Actions, MenuInput, Rebinding},
+    resume,
+    theme::{self, Palette},
+    AppState, Config, NewGameEvent, PieceFont, ResizeBoardEvent, Resumed,
+};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OpenSettingsEvent>()
+            .add_systems(Update, open_settings)
+            .add_systems(OnEnter(AppState::Settings), create_settings)
+            .add_systems(
+                OnExit(AppState::Settings),
+                (apply_settings, remove_settings),
+            )
+            .add_systems(
+                Update,
+                (settings_input, settings_buttons, update_settings_ui)
+                    .chain()
+                    .before(rebind_input)
+                    .run_if(in_state(AppState::Settings)),
+            );
+    }
+}
+
+// `new_game` turns the screen into the custom board setup: leaving it starts a game.
+#[derive(Event, Default)]
+pub struct OpenSettingsEvent {
+    pub new_game: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    BoardSize,
+    AnimationSpeed,
+    FourChance,
+    Theme,
+    Volume,
+    Effects,
+    Music,
+    KeyBindings,
+    Back,
+}
+
+impl Row {
+    const ALL: [Row; 9] = [
+        Row::BoardSize,
+        Row::AnimationSpeed,
+        Row::FourChance,
+        Row::Theme,
+        Row::Volume,
+        Row::Effects,
+        Row::Music,
+        Row::KeyBindings,
+        Row::Back,
+    ];
+
+    fn label(self, new_game: bool) -> &'static str {
+        match self {
+            Row::Back if new_game => "Start",
+            Row::BoardSize => "Board size",
+            Row::AnimationSpeed => "Animation",
+            Row::FourChance => "Chance of 4",
+            Row::Theme => "Theme",
+            Row::Volume => "Volume",
+            Row::Effects => "Effects",
+            Row::Music => "Music",
+            Row::KeyBindings => "Key bindings",
+            Row::Back => "Back",
+        }
+    }
+}
+
+// The settings being edited, applied when the screen closes.
+#[derive(Resource)]
+struct SettingsMenu {
+    selected: usize,
+    previous: AppState,
+    new_game: bool,
+    // set by the "Start" row of the custom board setup
+    start: bool,
+    draft: Settings,
+}
+
+#[derive(Component)]
+struct SettingsUi;
+
+#[derive(Component)]
+struct RowLabel(Row);
+
+#[derive(Component)]
+struct RowValue(Row);
+
+#[derive(Component, Clone, Copy)]
+enum SettingsButton {
+    Prev(Row),
+    Next(Row),
+    Activate(Row),
+}
+
+fn open_settings(
+    mut commands: Commands,
+    mut open_event: EventReader<OpenSettingsEvent>,
+    actions: Actions,
+    state: Res<State<AppState>>,
+    config: Res<Config>,
+    speed: Res<AnimationSpeed>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let new_game = match open_event.read().last() {
+        Some(event) => event.new_game,
+        None if actions.just_pressed(Action::Settings) => false,
+        None => return,
+    };
+    let previous = state.get().clone();
+    if !matches!(
+        previous,
+        AppState::Input | AppState::GameOver | AppState::Won | AppState::Menu | AppState::Paused
+    ) {
+        return;
+    }
+    commands.insert_resource(SettingsMenu {
+        selected: 0,
+        previous,
+        new_game,
+        start: false,
+        draft: Settings {
+            animation_speed: *speed,
+            ..config.settings()
+        },
+    });
+    next_state.set(AppState::Settings);
+}
+
+// Steps the value on `row` by `step`, staying in range.
+fn change(draft: &mut Settings, row: Row, step: i32) {
+    match row {
+        Row::BoardSize => draft.size = (draft.size + step).clamp(3, 8),
+        Row::AnimationSpeed => {
+            let speeds = AnimationSpeed::ALL;
+            let index = speeds
+                .iter()
+                .position(|speed| *speed == draft.animation_speed)
+                .unwrap_or(0) as i32;
+            draft.animation_speed =
+                speeds[(index + step).clamp(0, speeds.len() as i32 - 1) as usize];
+        }
+        Row::FourChance => {
+            let percent = (draft.spawn_four_chance * 100.0).round() as i32 + 5 * step;
+            draft.spawn_four_chance = percent.clamp(0, 100) as f32 / 100.0;
+        }
+        Row::Volume => step_volume(&mut draft.master_volume, step),
+        Row::Effects => step_volume(&mut draft.sfx_volume, step),
+        Row::Music => step_volume(&mut draft.music_volume, step),
+        Row::Theme => {
+            // a theme of the player's own counts as the first one
+            let themes = theme::BUILT_IN;
+            let index = themes
+                .iter()
+                .position(|name| *name == draft.theme)
+                .unwrap_or(0) as i32;
+            draft.theme =
+                themes[(index + step).clamp(0, themes.len() as i32 - 1) as usize].to_string();
+        }
+        Row::KeyBindings | Row::Back => {}
+    }
+}
+
+// in steps of 10%
+fn step_volume(volume: &mut f32, step: i32) {
+    let percent = (*volume * 10.0).round() as i32 + step;
+    *volume = percent.clamp(0, 10) as f32 / 10.0;
+}
+
+fn activate(
+    commands: &mut Commands,
+    row: Row,
+    next_state: &mut NextState<AppState>,
+    resumed: &mut Resumed,
+    menu: &mut SettingsMenu,
+) {
+    match row {
+        Row::KeyBindings => commands.insert_resource(Rebinding::default()),
+        Row::Back if menu.new_game => {
+            menu.start = true;
+            next_state.set(AppState::Input);
+        }
+        Row::Back => resume(&menu.previous, resumed, next_state),
+        _ => {}
+    }
+}
+
+fn settings_input(
+    mut commands: Commands,
+    actions: Actions,
+    menu_input: MenuInput,
+    mut menu: ResMut<SettingsMenu>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resumed: ResMut<Resumed>,
+) {
+    let rows = Row::ALL.len();
+    if actions.just_pressed(Action::Up) {
+        menu.selected = (menu.selected + rows - 1) % rows;
+    }
+    if actions.just_pressed(Action::Down) {
+        menu.selected = (menu.selected + 1) % rows;
+    }
+    let row = Row::ALL[menu.selected];
+    if actions.just_pressed(Action::Left) {
+        change(&mut menu.draft, row, -1);
+    }
+    if actions.just_pressed(Action::Right) {
+        change(&mut menu.draft, row, 1);
+    }
+    if menu_input.confirm() {
+        activate(&mut commands, row, &mut next_state, &mut resumed, &mut menu);
+    }
+    if menu_input.back() {
+        resume(&menu.previous, &mut resumed, &mut next_state);
+    }
+}
+
+fn settings_buttons(
+    mut commands: Commands,
+    interactions: Query<(&Interaction, &SettingsButton), Changed<Interaction>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resumed: ResMut<Resumed>,
+    rebinding: Option<Res<Rebinding>>,
+) {
+    if rebinding.is_some() {
+        return;
+    }
+    for (interaction, button) in interactions.iter() {
+        let (SettingsButton::Prev(row) | SettingsButton::Next(row) | SettingsButton::Activate(row)) =
+            *button;
+        match interaction {
+            Interaction::Hovered => {
+                menu.selected = Row::ALL.iter().position(|other| *other == row).unwrap_or(0);
+            }
+            Interaction::Pressed => match button {
+                SettingsButton::Prev(row) => change(&mut menu.draft, *row, -1),
+                SettingsButton::Next(row) => change(&mut menu.draft, *row, 1),
+                SettingsButton::Activate(row) => activate(
+                    &mut commands,
+                    *row,
+                    &mut next_state,
+                    &mut resumed,
+                    &mut menu,
+                ),
+            },
+            Interaction::None => {}
+        }
+    }
+}
+
+fn value_text(draft: &Settings, row: Row) -> String {
+    match row {
+        Row::BoardSize => format!("{0}x{0}", draft.size),
+        Row::AnimationSpeed => format!("{:?}", draft.animation_speed),
+        Row::FourChance => format!("{:.0}%", draft.spawn_four_chance * 100.0),
+        Row::Theme => draft.theme.clone(),
+        Row::Volume => format!("{:.0}%", draft.master_volume * 100.0),
+        Row::Effects => format!("{:.0}%", draft.sfx_volume * 100.0),
+        Row::Music => format!("{:.0}%", draft.music_volume * 100.0),
+        Row::KeyBindings | Row::Back => String::new(),
+    }
+}
+
+fn update_settings_ui(
+    menu: Res<SettingsMenu>,
+    palette: Res<Palette>,
+    mut labels: Query<(&RowLabel, &mut Text), Without<RowValue>>,
+    mut values: Query<(&RowValue, &mut Text), Without<RowLabel>>,
+) {
+    let selected = Row::ALL[menu.selected];
+    for (label, mut text) in labels.iter_mut() {
+        text.sections[0].style.color = if label.0 == selected {
+            palette.accent
+        } else {
+            palette.text
+        };
+    }
+    for (value, mut text) in values.iter_mut() {
+        text.sections[0].value = value_text(&menu.draft, value.0);
+    }
+}
+
+fn create_settings(
+    mut commands: Commands,
+    font: Res<PieceFont>,
+    palette: Res<Palette>,
+    menu: Res<SettingsMenu>,
+) {
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone_weak(),
+        font_size: size,
+        color,
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: palette.background.with_alpha(0.9).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            SettingsUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "SETTINGS",
+                style(80.0, palette.accent),
+            ));
+            for row in Row::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(800.0),
+                            height: Val::Px(55.0),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::SpaceBetween,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        let value = value_text(&menu.draft, row);
+                        if matches!(row, Row::KeyBindings | Row::Back) {
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        background_color: Color::NONE.into(),
+                                        ..default()
+                                    },
+                                    SettingsButton::Activate(row),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        TextBundle::from_section(
+                                            row.label(menu.new_game),
+                                            style(50.0, palette.text),
+                                        ),
+                                        RowLabel(row),
+                                    ));
+                                });
+                            return;
+                        }
+                        parent.spawn((
+                            TextBundle::from_section(
+                                row.label(menu.new_game),
+                                style(50.0, palette.text),
+                            ),
+                            RowLabel(row),
+                        ));
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(400.0),
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::SpaceBetween,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                for (label, button) in [
+                                    ("<", Some(SettingsButton::Prev(row))),
+                                    ("", None),
+                                    (">", Some(SettingsButton::Next(row))),
+                                ] {
+                                    match button {
+                                        Some(button) => {
+                                            parent
+                                                .spawn((
+                                                    ButtonBundle {
+                                                        background_color: Color::NONE.into(),
+                                                        ..default()
+                                                    },
+                                                    button,
+                                                ))
+                                                .with_children(|parent| {
+                                                    parent.spawn(TextBundle::from_section(
+                                                        label,
+                                                        style(50.0, palette.button),
+                                                    ));
+                                                });
+                                        }
+                                        None => {
+                                            parent.spawn((
+                                                TextBundle::from_section(
+                                                    value.clone(),
+                                                    style(50.0, palette.text),
+                                                ),
+                                                RowValue(row),
+                                            ));
+                                        }
+                                    }
+                                }
+                            });
+                    });
+            }
+            parent.spawn(TextBundle::from_section(
+                "arrows: choose and change  Enter: select  Esc: back",
+                style(30.0, palette.button),
+            ));
+        });
+}
+
+fn remove_settings(mut commands: Commands, query: Query<Entity, With<SettingsUi>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<SettingsMenu>();
+}
+
+// A new board size only takes effect with a new game on a rebuilt board. Spawn chances
+// apply from the next game on.
+fn apply_settings(
+    menu: Res<SettingsMenu>,
+    mut config: ResMut<Config>,
+    mut speed: ResMut<AnimationSpeed>,
+    mut resize_event: EventWriter<ResizeBoardEvent>,
+    mut new_game_event: EventWriter<NewGameEvent>,
+) {
+    let resize = menu.draft.size != config.size;
+    config.apply_settings(&menu.draft);
+    *speed = config.animation_speed;
+    if resize {
+        config.fit_board();
+        resize_event.send(ResizeBoardEvent);
+    }
+    if resize || menu.start {
+        new_game_event.send(NewGameEvent);
+    }
+    config.save_settings();
+}
@@ -0,0 +1,359 @@
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, Decodable, Source, Volume},
+    prelude::*,
+};
+use bevy_2048::game::MoveEvent;
+
+use crate::{
+    controls::{Action, Actions},
+    not_resumed, AddPieceEvent, AppState, Config, InvalidMoveEvent, SetValueEvent,
+};
+
+const SAMPLE_RATE: u32 = 44_100;
+// merges go up this many semitones for every doubling of the tile
+const SEMITONES_PER_MERGE: f32 = 2.0;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Synth>()
+            .add_audio_source::<Synth>()
+            .init_resource::<Muted>()
+            .add_systems(Startup, (create_sounds, start_music).chain())
+            .add_systems(Update, mute_input)
+            .add_systems(
+                Update,
+                music_volume.run_if(resource_changed::<Config>.or_else(resource_changed::<Muted>)),
+            )
+            .add_systems(
+                Update,
+                (move_sound, invalid_move_sound, merge_sound, spawn_sound),
+            )
+            .add_systems(OnEnter(AppState::Won), win_sound.run_if(not_resumed))
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                game_over_sound.run_if(not_resumed),
+            );
+    }
+}
+
+// Mono samples made at startup, so the game needs no audio files.
+#[derive(Asset, TypePath, Clone)]
+pub struct Synth {
+    samples: Arc<[f32]>,
+}
+
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    next: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.next).copied();
+        self.next += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.next))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for Synth {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> SynthDecoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            next: 0,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Sounds {
+    slide: Handle<Synth>,
+    merge: Handle<Synth>,
+    spawn: Handle<Synth>,
+    invalid: Handle<Synth>,
+    win: Handle<Synth>,
+    game_over: Handle<Synth>,
+    music: Handle<Synth>,
+}
+
+#[derive(Resource, Default)]
+struct Muted(bool);
+
+#[derive(Component)]
+struct Music;
+
+// `sample` gets the time in seconds and returns a value in -1..1.
+fn synth(seconds: f32, sample: impl Fn(f32) -> f32) -> Synth {
+    let len = (seconds * SAMPLE_RATE as f32) as usize;
+    Synth {
+        samples: (0..len)
+            .map(|i| sample(i as f32 / SAMPLE_RATE as f32))
+            .collect(),
+    }
+}
+
+fn sine(freq: f32, t: f32) -> f32 {
+    (TAU * freq * t).sin()
+}
+
+fn triangle(freq: f32, t: f32) -> f32 {
+    let phase = (freq * t).fract();
+    4.0 * (phase - 0.5).abs() - 1.0
+}
+
+fn square(freq: f32, t: f32) -> f32 {
+    if (freq * t).fract() < 0.5 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+// Cheap deterministic noise, the same hiss every time.
+fn noise(t: f32) -> f32 {
+    let x = ((t * SAMPLE_RATE as f32) as u32).wrapping_mul(2_654_435_761);
+    let x = (x ^ (x >> 15)).wrapping_mul(2_246_822_519);
+    (x >> 8) as f32 / (1 << 23) as f32 - 1.0
+}
+
+// Plays `notes` (frequency, seconds) one after the other.
+fn melody(notes: &[(f32, f32)], wave: fn(f32, f32) -> f32, volume: f32) -> Synth {
+    let total = notes.iter().map(|(_, length)| length).sum();
+    let notes = notes.to_vec();
+    synth(total, move |t| {
+        let mut start = 0.0;
+        for (freq, length) in &notes {
+            if t < start + length {
+                let local = t - start;
+                // short attack and release so notes don't click
+                let envelope = (local / 0.01).min(1.0) * ((start + length - t) / 0.03).min(1.0);
+                return wave(*freq, local) * envelope * volume;
+            }
+            start += length;
+        }
+        0.0
+    })
+}
+
+fn create_sounds(mut commands: Commands, mut synths: ResMut<Assets<Synth>>) {
+    let slide = synth(0.07, |t| {
+        let envelope = (1.0 - t / 0.07).powi(2);
+        (noise(t) * 0.5 + noise(t - 1.0 / SAMPLE_RATE as f32) * 0.5) * envelope * 0.25
+    });
+    let merge = synth(0.18, |t| {
+        let envelope = (-t * 25.0).exp();
+        (sine(440.0, t) + 0.3 * sine(880.0, t)) * envelope * 0.4
+    });
+    let spawn = synth(0.06, |t| {
+        let freq = 600.0 + 300.0 * t / 0.06;
+        sine(freq, t) * (1.0 - t / 0.06) * 0.2
+    });
+    let invalid = synth(0.16, |t| {
+        // two short buzzes
+        let gate = if (0.06..=0.1).contains(&t) { 0.0 } else { 1.0 };
+        square(110.0, t) * gate * 0.12
+    });
+    let win = melody(
+        &[(523.3, 0.12), (659.3, 0.12), (784.0, 0.12), (1046.5, 0.36)],
+        triangle,
+        0.4,
+    );
+    let game_over = melody(
+        &[(392.0, 0.18), (329.6, 0.18), (261.6, 0.18), (196.0, 0.5)],
+        triangle,
+        0.4,
+    );
+    // C, Am, F, G arpeggios in eighth notes at 100 bpm
+    let chords: [[f32; 3]; 4] = [
+        [261.6, 329.6, 392.0],
+        [220.0, 261.6, 329.6],
+        [174.6, 220.0, 261.6],
+        [196.0, 246.9, 293.7],
+    ];
+    let notes: Vec<(f32, f32)> = chords
+        .iter()
+        .flat_map(|chord| {
+            [0, 1, 2, 1, 0, 1, 2, 1]
+                .into_iter()
+                .cycle()
+                .take(16)
+                .map(|i| (chord[i], 0.3))
+        })
+        .collect();
+    let music = melody(&notes, triangle, 0.15);
+    commands.insert_resource(Sounds {
+        slide: synths.add(slide),
+        merge: synths.add(merge),
+        spawn: synths.add(spawn),
+        invalid: synths.add(invalid),
+        win: synths.add(win),
+        game_over: synths.add(game_over),
+        music: synths.add(music),
+    });
+}
+
+fn sfx_volume(config: &Config, muted: &Muted) -> f32 {
+    if muted.0 {
+        0.0
+    } else {
+        config.master_volume * config.sfx_volume
+    }
+}
+
+fn play(commands: &mut Commands, sound: &Handle<Synth>, volume: f32, speed: f32) {
+    if volume <= 0.0 {
+        return;
+    }
+    commands.spawn(AudioSourceBundle {
+        source: sound.clone(),
+        settings: PlaybackSettings::DESPAWN
+            .with_volume(Volume::new(volume))
+            .with_speed(speed),
+    });
+}
+
+fn start_music(mut commands: Commands, sounds: Res<Sounds>, config: Res<Config>) {
+    commands.spawn((
+        AudioSourceBundle {
+            source: sounds.music.clone(),
+            settings: PlaybackSettings::LOOP
+                .with_volume(Volume::new(config.master_volume * config.music_volume)),
+        },
+        Music,
+    ));
+}
+
+fn music_volume(config: Res<Config>, muted: Res<Muted>, sinks: Query<&AudioSink, With<Music>>) {
+    let volume = if muted.0 {
+        0.0
+    } else {
+        config.master_volume * config.music_volume
+    };
+    for sink in sinks.iter() {
+        sink.set_volume(volume);
+    }
+}
+
+fn mute_input(actions: Actions, mut muted: ResMut<Muted>) {
+    if actions.just_pressed(Action::Mute) {
+        muted.0 = !muted.0;
+    }
+}
+
+fn move_sound(
+    mut commands: Commands,
+    mut move_event: EventReader<MoveEvent>,
+    sounds: Res<Sounds>,
+    config: Res<Config>,
+    muted: Res<Muted>,
+) {
+    if move_event.read().count() > 0 {
+        play(
+            &mut commands,
+            &sounds.slide,
+            sfx_volume(&config, &muted),
+            1.0,
+        );
+    }
+}
+
+fn invalid_move_sound(
+    mut commands: Commands,
+    mut invalid_event: EventReader<InvalidMoveEvent>,
+    sounds: Res<Sounds>,
+    config: Res<Config>,
+    muted: Res<Muted>,
+) {
+    if invalid_event.read().count() > 0 {
+        play(
+            &mut commands,
+            &sounds.invalid,
+            sfx_volume(&config, &muted),
+            1.0,
+        );
+    }
+}
+
+fn merge_sound(
+    mut commands: Commands,
+    mut set_value_event: EventReader<SetValueEvent>,
+    sounds: Res<Sounds>,
+    config: Res<Config>,
+    muted: Res<Muted>,
+) {
+    // one sound for the biggest merge of the move
+    let Some(value) = set_value_event.read().map(|event| event.value).max() else {
+        return;
+    };
+    let semitones = (value.max(4).ilog2() - 2) as f32 * SEMITONES_PER_MERGE;
+    let speed = 2f32.powf(semitones / 12.0);
+    play(
+        &mut commands,
+        &sounds.merge,
+        sfx_volume(&config, &muted),
+        speed,
+    );
+}
+
+fn spawn_sound(
+    mut commands: Commands,
+    mut add_event: EventReader<AddPieceEvent>,
+    sounds: Res<Sounds>,
+    config: Res<Config>,
+    muted: Res<Muted>,
+) {
+    if add_event.read().count() > 0 {
+        play(
+            &mut commands,
+            &sounds.spawn,
+            sfx_volume(&config, &muted),
+            1.0,
+        );
+    }
+}
+
+fn win_sound(mut commands: Commands, sounds: Res<Sounds>, config: Res<Config>, muted: Res<Muted>) {
+    play(&mut commands, &sounds.win, sfx_volume(&config, &muted), 1.0);
+}
+
+fn game_over_sound(
+    mut commands: Commands,
+    sounds: Res<Sounds>,
+    config: Res<Config>,
+    muted: Res<Muted>,
+) {
+    play(
+        &mut commands,
+        &sounds.game_over,
+        sfx_volume(&config, &muted),
+        1.0,
+    );
+}
@@ -0,0 +1,185 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_2048::game::MoveEvent;
+
+use crate::{move_direction, Config, MoveInputEvent};
+
+// how long the last gesture stays on screen with `swipe_debug`
+const DEBUG_SECONDS: f32 = 1.0;
+
+pub struct SwipePlugin;
+
+impl Plugin for SwipePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Swipe>()
+            .add_systems(Update, swipe_input)
+            .add_systems(
+                Update,
+                draw_swipe
+                    .after(swipe_input)
+                    .run_if(|config: Res<Config>| config.swipe_debug),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pointer {
+    Mouse,
+    Touch(u64),
+}
+
+// The drag in progress, in window coordinates, and the last one recognised.
+#[derive(Resource, Default)]
+struct Swipe {
+    drag: Option<Drag>,
+    last: Option<Gesture>,
+}
+
+struct Drag {
+    pointer: Pointer,
+    start: Vec2,
+    current: Vec2,
+    started: f32,
+}
+
+struct Gesture {
+    start: Vec2,
+    end: Vec2,
+    event: Option<MoveEvent>,
+    ended: f32,
+}
+
+fn swipe_input(
+    touches: Res<Touches>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+    config: Res<Config>,
+    mut swipe: ResMut<Swipe>,
+    mut move_input: EventWriter<MoveInputEvent>,
+) {
+    let cursor = windows.get_single().ok().and_then(|w| w.cursor_position());
+    let now = time.elapsed_seconds();
+    if swipe.drag.is_none() {
+        let pressed = if let Some(touch) = touches.iter_just_pressed().next() {
+            Some((Pointer::Touch(touch.id()), touch.position()))
+        } else if mouse.just_pressed(MouseButton::Left) {
+            cursor.map(|pos| (Pointer::Mouse, pos))
+        } else {
+            None
+        };
+        if let Some((pointer, start)) = pressed {
+            swipe.drag = Some(Drag {
+                pointer,
+                start,
+                current: start,
+                started: now,
+            });
+        }
+        return;
+    }
+
+    let Some(drag) = swipe.drag.as_mut() else {
+        return;
+    };
+    let released = match drag.pointer {
+        Pointer::Touch(id) => {
+            if touches.just_canceled(id) {
+                swipe.drag = None;
+                return;
+            }
+            if let Some(touch) = touches.get_pressed(id) {
+                drag.current = touch.position();
+            }
+            match touches.get_released(id) {
+                Some(touch) => {
+                    drag.current = touch.position();
+                    true
+                }
+                None => false,
+            }
+        }
+        Pointer::Mouse => {
+            // the cursor may have left the window, keep the last known position
+            if let Some(pos) = cursor {
+                drag.current = pos;
+            }
+            !mouse.pressed(MouseButton::Left)
+        }
+    };
+    if !released {
+        return;
+    }
+
+    let event = recognise(drag.current - drag.start, now - drag.started, &config);
+    if let Some(event) = event {
+        move_input.send(MoveInputEvent(event));
+    }
+    swipe.last = Some(Gesture {
+        start: drag.start,
+        end: drag.current,
+        event,
+        ended: now,
+    });
+    swipe.drag = None;
+}
+
+// `delta` in window coordinates, where y grows downwards.
+fn recognise(delta: Vec2, seconds: f32, config: &Config) -> Option<MoveEvent> {
+    let distance = delta.length();
+    if distance < config.swipe_dead_zone
+        || distance / seconds.max(0.001) < config.swipe_min_velocity
+    {
+        return None;
+    }
+    if delta.x.abs() > delta.y.abs() {
+        Some(if delta.x > 0.0 {
+            MoveEvent::Right
+        } else {
+            MoveEvent::Left
+        })
+    } else {
+        Some(if delta.y > 0.0 {
+            MoveEvent::Down
+        } else {
+            MoveEvent::Up
+        })
+    }
+}
+
+fn draw_swipe(
+    swipe: Res<Swipe>,
+    config: Res<Config>,
+    time: Res<Time>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    let Ok((camera, transform)) = cameras.get_single() else {
+        return;
+    };
+    let to_world = |pos: Vec2| camera.viewport_to_world_2d(transform, pos);
+    if let Some(drag) = &swipe.drag {
+        if let (Some(start), Some(current)) = (to_world(drag.start), to_world(drag.current)) {
+            gizmos.circle_2d(start, config.swipe_dead_zone, Color::srgb(0.6, 0.6, 0.6));
+            gizmos.line_2d(start, current, Color::srgb(0.3, 0.3, 0.3));
+        }
+    }
+    if let Some(gesture) = &swipe.last {
+        if time.elapsed_seconds() - gesture.ended > DEBUG_SECONDS {
+            return;
+        }
+        let (Some(start), Some(end)) = (to_world(gesture.start), to_world(gesture.end)) else {
+            return;
+        };
+        let Some(event) = gesture.event else {
+            gizmos.line_2d(start, end, Color::srgb(0.8, 0.1, 0.1));
+            return;
+        };
+        gizmos.line_2d(start, end, Color::srgb(0.6, 0.6, 0.6));
+        // the recognised direction, along the dominant axis
+        gizmos.arrow_2d(
+            start,
+            start + move_direction(event) * (end - start).length(),
+            Color::srgb(0.1, 0.6, 0.2),
+        );
+    }
+}
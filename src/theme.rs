@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+use crate::{Config, PieceFont, PieceMarker, TitleFont, Value};
+
+// the themes shipped in assets/themes, in the order the settings screen cycles them
+pub const BUILT_IN: [&str; 4] = ["classic", "dark", "high-contrast", "colour-blind"];
+// room for the label inside the 150px "piece" slice
+const TILE_TEXT_WIDTH: f32 = 120.0;
+// width of a digit for each pixel of font size, a little over what mai10 needs
+const GLYPH_WIDTH: f32 = 0.45;
+// turn of the colour wheel between two generated tiles, keeps neighbours apart
+const GOLDEN_ANGLE: f32 = 137.508;
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Palette>()
+            .add_systems(Update, select_theme.run_if(resource_changed::<Config>))
+            .add_systems(Update, apply_theme.after(select_theme))
+            .add_systems(
+                Update,
+                (recolor_board, recolor_text)
+                    .after(apply_theme)
+                    .run_if(resource_changed::<Palette>),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TileColors {
+    pub color: Color,
+    pub text: Color,
+}
+
+// The colours and sizes of the theme in use, kept as a resource so the board and the
+// UI can be drawn before the theme file has loaded.
+#[derive(Resource, Clone, Debug)]
+pub struct Palette {
+    pub background: Color,
+    // tint of the empty cells, white keeps the sprite as drawn
+    pub empty_cell: Color,
+    pub text: Color,
+    pub text_dim: Color,
+    pub button: Color,
+    pub accent: Color,
+    pub tile_font_size: f32,
+    pub title_font_size: f32,
+    tiles: HashMap<i32, TileColors>,
+    // for values the theme has no colour for
+    generated: Generated,
+}
+
+// Tiles past the theme's list get a hue of their own at this saturation and lightness.
+#[derive(Clone, Copy, Debug)]
+struct Generated {
+    saturation: f32,
+    lightness: f32,
+    text: Color,
+}
+
+impl Palette {
+    pub fn tile(&self, value: i32) -> TileColors {
+        if let Some(tile) = self.tiles.get(&value) {
+            return *tile;
+        }
+        let exponent = value.max(1).ilog2() as f32;
+        TileColors {
+            color: Color::hsl(
+                exponent * GOLDEN_ANGLE % 360.0,
+                self.generated.saturation,
+                self.generated.lightness,
+            ),
+            text: self.generated.text,
+        }
+    }
+
+    // Shrinks the font for long labels so they stay inside the tile.
+    pub fn tile_font_size(&self, label: &str) -> f32 {
+        let fit = TILE_TEXT_WIDTH / (label.chars().count() as f32 * GLYPH_WIDTH);
+        self.tile_font_size.min(fit)
+    }
+
+    pub fn ink(&self, ink: Ink) -> Color {
+        match ink {
+            Ink::Text => self.text,
+            Ink::Dim => self.text_dim,
+            Ink::Button => self.button,
+            Ink::Title => self.accent,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let white = Color::WHITE;
+        let tiles = [
+            (2, Color::srgb_u8(255, 209, 0)),
+            (4, Color::srgb_u8(255, 132, 38)),
+            (8, Color::srgb_u8(214, 36, 17)),
+            (16, Color::srgb_u8(255, 128, 164)),
+            (32, Color::srgb_u8(255, 38, 116)),
+            (64, Color::srgb_u8(191, 255, 60)),
+            (128, Color::srgb_u8(16, 210, 117)),
+            (256, Color::srgb_u8(40, 200, 225)),
+            (512, Color::srgb_u8(31, 85, 148)),
+            (1024, Color::srgb_u8(67, 0, 103)),
+            (2048, Color::srgb_u8(148, 33, 106)),
+            (4096, Color::srgb_u8(155, 124, 68)),
+            (8192, Color::srgb_u8(199, 113, 244)),
+            (16384, Color::srgb_u8(103, 40, 225)),
+            (32768, Color::srgb_u8(237, 198, 131)),
+            (65536, Color::srgb_u8(144, 52, 192)),
+        ];
+        Self {
+            background: white,
+            empty_cell: white,
+            text: Color::srgb(0.3, 0.3, 0.3),
+            text_dim: Color::srgb(0.6, 0.6, 0.6),
+            button: Color::srgb(0.75, 0.75, 0.75),
+            accent: Color::srgb(0.5, 0.1, 0.4),
+            tile_font_size: 48.0,
+            title_font_size: 120.0,
+            tiles: tiles
+                .into_iter()
+                .map(|(value, color)| (value, TileColors { color, text: white }))
+                .collect(),
+            generated: Generated {
+                saturation: 0.6,
+                lightness: 0.35,
+                text: white,
+            },
+        }
+    }
+}
+
+// What a themed text section is drawn with. `Title` is the accent colour in the title font.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ink {
+    Text,
+    Dim,
+    Button,
+    Title,
+}
+
+// Text that is recoloured when the theme changes, one ink per section.
+#[derive(Component)]
+pub struct Themed(pub Vec<Ink>);
+
+#[derive(Component)]
+pub struct EmptyCell;
+
+#[derive(Asset, TypePath, Clone, Debug)]
+pub struct Theme {
+    palette: Palette,
+    font: Handle<Font>,
+    title_font: Handle<Font>,
+}
+
+// The file format, colours as "#rrggbb" or "#rrggbbaa".
+#[derive(Deserialize)]
+struct ThemeFile {
+    background: String,
+    empty_cell: String,
+    text: String,
+    text_dim: String,
+    button: String,
+    accent: String,
+    font: String,
+    title_font: String,
+    tile_font_size: f32,
+    title_font_size: f32,
+    tiles: BTreeMap<i32, TileFile>,
+    generated: GeneratedFile,
+}
+
+#[derive(Deserialize)]
+struct TileFile {
+    color: String,
+    text: String,
+}
+
+fn parse_color(hex: &str) -> Result<Color, String> {
+    Srgba::hex(hex)
+        .map(Color::from)
+        .map_err(|err| format!("invalid colour {hex:?}: {err}"))
+}
+
+#[derive(Deserialize)]
+struct GeneratedFile {
+    saturation: f32,
+    lightness: f32,
+    text: String,
+}
+
+impl TileFile {
+    fn colors(&self) -> Result<TileColors, String> {
+        Ok(TileColors {
+            color: parse_color(&self.color)?,
+            text: parse_color(&self.text)?,
+        })
+    }
+}
+
+#[derive(Default)]
+struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Theme, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: ThemeFile = ron::de::from_bytes(&bytes)?;
+        let tiles = file
+            .tiles
+            .iter()
+            .map(|(value, tile)| Ok((*value, tile.colors()?)))
+            .collect::<Result<_, String>>()?;
+        Ok(Theme {
+            palette: Palette {
+                background: parse_color(&file.background)?,
+                empty_cell: parse_color(&file.empty_cell)?,
+                text: parse_color(&file.text)?,
+                text_dim: parse_color(&file.text_dim)?,
+                button: parse_color(&file.button)?,
+                accent: parse_color(&file.accent)?,
+                tile_font_size: file.tile_font_size,
+                title_font_size: file.title_font_size,
+                tiles,
+                generated: Generated {
+                    saturation: file.generated.saturation,
+                    lightness: file.generated.lightness,
+                    text: parse_color(&file.generated.text)?,
+                },
+            },
+            font: load_context.load(file.font),
+            title_font: load_context.load(file.title_font),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct CurrentTheme {
+    name: String,
+    handle: Handle<Theme>,
+}
+
+fn select_theme(
+    mut commands: Commands,
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    current: Option<Res<CurrentTheme>>,
+) {
+    if current.is_some_and(|current| current.name == config.theme) {
+        return;
+    }
+    commands.insert_resource(CurrentTheme {
+        name: config.theme.clone(),
+        handle: asset_server.load(format!("themes/{}.theme.ron", config.theme)),
+    });
+}
+
+// Runs when a theme is picked and again whenever its file changes on disk.
+fn apply_theme(
+    mut asset_events: EventReader<AssetEvent<Theme>>,
+    current: Option<Res<CurrentTheme>>,
+    themes: Res<Assets<Theme>>,
+    mut palette: ResMut<Palette>,
+    mut clear_color: ResMut<ClearColor>,
+    mut font: ResMut<PieceFont>,
+    mut title_font: ResMut<TitleFont>,
+) {
+    let Some(current) = current else {
+        return;
+    };
+    let loaded = asset_events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == current.handle.id()
+        }
+        _ => false,
+    });
+    if !loaded && !current.is_changed() {
+        return;
+    }
+    let Some(theme) = themes.get(&current.handle) else {
+        return;
+    };
+    *palette = theme.palette.clone();
+    clear_color.0 = palette.background;
+    font.0 = theme.font.clone();
+    title_font.0 = theme.title_font.clone();
+}
+
+fn recolor_board(
+    palette: Res<Palette>,
+    font: Res<PieceFont>,
+    pieces: Query<(&Value, &Children), With<PieceMarker>>,
+    mut sprites: Query<&mut Sprite, Without<EmptyCell>>,
+    mut texts: Query<&mut Text>,
+    mut cells: Query<&mut Sprite, With<EmptyCell>>,
+) {
+    for (value, children) in pieces.iter() {
+        let tile = palette.tile(value.0);
+        for child in children.iter() {
+            if let Ok(mut sprite) = sprites.get_mut(*child) {
+                sprite.color = tile.color;
+            }
+            if let Ok(mut text) = texts.get_mut(*child) {
+                for section in text.sections.iter_mut() {
+                    section.style.color = tile.text;
+                    section.style.font = font.0.clone_weak();
+                    section.style.font_size = palette.tile_font_size(&section.value);
+                }
+            }
+        }
+    }
+    for mut sprite in cells.iter_mut() {
+        sprite.color = palette.empty_cell;
+    }
+}
+
+fn recolor_text(
+    palette: Res<Palette>,
+    font: Res<PieceFont>,
+    title_font: Res<TitleFont>,
+    mut texts: Query<(&Themed, &mut Text)>,
+) {
+    for (themed, mut text) in texts.iter_mut() {
+        for (section, ink) in text.sections.iter_mut().zip(&themed.0) {
+            section.style.color = palette.ink(*ink);
+            if *ink == Ink::Title {
+                section.style.font = title_font.0.clone_weak();
+                section.style.font_size = palette.title_font_size;
+            } else {
+                section.style.font = font.0.clone_weak();
+            }
+        }
+    }
+}
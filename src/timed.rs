@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    new_game_event, replay::ReplayPlayer, AppState, Config, GameMode, NewGameEvent, SetValueEvent,
+};
+
+// seconds given back for a merge of `time_bonus_from`, and again for every doubling above it
+const BONUS_SECS: u64 = 2;
+
+pub struct TimedPlugin;
+
+impl Plugin for TimedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            start_countdown
+                .after(new_game_event)
+                .run_if(on_event::<NewGameEvent>()),
+        )
+        .add_systems(
+            Update,
+            (
+                tick_countdown,
+                time_bonus.run_if(on_event::<SetValueEvent>()),
+            )
+                .run_if(resource_exists::<Countdown>)
+                .run_if(not(resource_exists::<ReplayPlayer>)),
+        );
+    }
+}
+
+// Time left in a timed game, only there while one is being played.
+#[derive(Resource)]
+pub struct Countdown(pub Duration);
+
+impl Countdown {
+    pub fn is_over(&self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+fn start_countdown(mut commands: Commands, mode: Res<GameMode>, config: Res<Config>) {
+    match *mode {
+        GameMode::Timed => {
+            commands.insert_resource(Countdown(Duration::from_secs(config.timed_secs)));
+        }
+        _ => commands.remove_resource::<Countdown>(),
+    }
+}
+
+// The clock only runs while tiles can move, so pausing or opening a menu keeps the time.
+fn tick_countdown(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut countdown: ResMut<Countdown>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !matches!(
+        state.get(),
+        AppState::Input | AppState::Anim | AppState::PostAnim
+    ) {
+        return;
+    }
+    countdown.0 = countdown.0.saturating_sub(time.delta());
+    // a move already sliding gets to finish first
+    if countdown.is_over() && *state.get() == AppState::Input {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+fn time_bonus(
+    mut set_value_event: EventReader<SetValueEvent>,
+    mut countdown: ResMut<Countdown>,
+    config: Res<Config>,
+) {
+    for event in set_value_event.read() {
+        if event.value >= config.time_bonus_from && !countdown.is_over() {
+            let doublings = (event.value.ilog2() - config.time_bonus_from.ilog2()) as u64;
+            countdown.0 += Duration::from_secs(BONUS_SECS * (doublings + 1));
+        }
+    }
+}
@@ -1,20 +1,71 @@
 use bevy::prelude::*;
 
-use crate::{AppState, HighScore, InitSet, NewGameEvent, PieceFont, Score, TitleFont};
+use crate::{
+    autoplay::HintEvent,
+    config::AnimationSpeed,
+    daily::DailyGame,
+    menu::OpenMenuEvent,
+    puzzle::CurrentPuzzle,
+    replay::GameRecord,
+    settings::OpenSettingsEvent,
+    theme::{Ink, Palette, Themed},
+    timed::Countdown,
+    AppState, Game, GameMode, HighScores, InitSet, KeepGoingEvent, NewGameEvent, PausedState,
+    PieceFont, Score, TitleFont,
+};
 
 pub struct GameUiPlugin;
 
 impl Plugin for GameUiPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, create_ui.after(InitSet))
-            .add_systems(Update, new_game_system)
+            .add_systems(Update, button_system)
             .add_systems(OnEnter(AppState::GameOver), create_game_over)
             .add_systems(OnExit(AppState::GameOver), remove_game_over)
-            .add_systems(Update, update_score_ui.run_if(resource_changed::<Score>));
+            .add_systems(OnEnter(AppState::Won), create_win)
+            .add_systems(OnExit(AppState::Won), remove_win)
+            .add_systems(OnEnter(AppState::Paused), create_pause)
+            .add_systems(OnExit(AppState::Paused), remove_pause)
+            .add_systems(
+                Update,
+                update_score_ui.run_if(
+                    resource_changed::<Score>
+                        .or_else(resource_changed::<HighScores>)
+                        .or_else(resource_changed::<GameMode>),
+                ),
+            )
+            .add_systems(
+                Update,
+                update_time_ui.run_if(resource_changed_or_removed::<Countdown>()),
+            )
+            .add_systems(
+                Update,
+                update_moves_ui.run_if(
+                    resource_changed::<GameRecord>
+                        .or_else(resource_changed_or_removed::<CurrentPuzzle>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                update_seed_ui.run_if(
+                    resource_changed::<Game>
+                        .or_else(resource_changed_or_removed::<DailyGame>())
+                        .or_else(resource_changed_or_removed::<CurrentPuzzle>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                update_speed_ui.run_if(resource_changed::<AnimationSpeed>),
+            );
     }
 }
 
-fn create_ui(mut commands: Commands, font: Res<PieceFont>, title_font: Res<TitleFont>) {
+fn create_ui(
+    mut commands: Commands,
+    font: Res<PieceFont>,
+    title_font: Res<TitleFont>,
+    palette: Res<Palette>,
+) {
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -27,134 +78,339 @@ fn create_ui(mut commands: Commands, font: Res<PieceFont>, title_font: Res<Title
             ..default()
         })
         .with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Percent(100.0),
-                        height: Val::Px(65.0),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    border_color: BorderColor(Color::NONE),
-                    background_color: Color::NONE.into(),
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "New Game (R)",
+            spawn_button(parent, "Hint (H)", UiButton::Hint, &font, &palette);
+            spawn_button(parent, "Settings (O)", UiButton::Settings, &font, &palette);
+            spawn_button(parent, "New Game (R)", UiButton::NewGame, &font, &palette);
+        });
+    //score
+    let score_entity = commands
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "Score: ",
                         TextStyle {
                             font: font.0.clone_weak(),
                             font_size: 50.0,
-                            color: Color::srgb(0.75, 0.75, 0.75),
+                            color: palette.text_dim,
                         },
-                    ));
-                });
-        });
-    //score
-    let score_entity = commands
-        .spawn(TextBundle {
-            text: Text::from_sections([
-                TextSection::new(
-                    "Score: ",
-                    TextStyle {
-                        font: font.0.clone_weak(),
-                        font_size: 50.0,
-                        color: Color::srgb(0.6, 0.6, 0.6),
-                    },
-                ),
-                TextSection::new(
-                    "0",
-                    TextStyle {
-                        font: font.0.clone_weak(),
-                        font_size: 50.0,
-                        color: Color::srgb(0.3, 0.3, 0.3),
-                    },
-                ),
-            ]),
-            style: Style {
-                position_type: PositionType::Absolute,
-                right: Val::Px(40.0),
-                bottom: Val::Percent(5.0),
+                    ),
+                    TextSection::new(
+                        "0",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(40.0),
+                    bottom: Val::Percent(5.0),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
         .id();
     let high_score_entity = commands
-        .spawn(TextBundle {
-            text: Text::from_sections([
-                TextSection::new(
-                    "High: ",
-                    TextStyle {
-                        font: font.0.clone_weak(),
-                        font_size: 50.0,
-                        color: Color::srgb(0.6, 0.6, 0.6),
-                    },
-                ),
-                TextSection::new(
-                    "0",
-                    TextStyle {
-                        font: font.0.clone_weak(),
-                        font_size: 50.0,
-                        color: Color::srgb(0.3, 0.3, 0.3),
-                    },
-                ),
-            ]),
-            style: Style {
-                position_type: PositionType::Absolute,
-                right: Val::Px(40.0),
-                bottom: Val::Percent(2.0),
-                /* left: Val::Percent(75.0),
-                top: Val::Percent(96.0), */
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "High: ",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text_dim,
+                        },
+                    ),
+                    TextSection::new(
+                        "0",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(40.0),
+                    bottom: Val::Percent(2.0),
+                    /* left: Val::Percent(75.0),
+                    top: Val::Percent(96.0), */
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
+        .id();
+    // only shown in timed games
+    let time_entity = commands
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "Time: ",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text_dim,
+                        },
+                    ),
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(40.0),
+                    bottom: Val::Percent(8.0),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
+        .id();
+    // puzzles show the moves left in place of the time
+    let moves_entity = commands
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "Moves: ",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text_dim,
+                        },
+                    ),
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 50.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(40.0),
+                    bottom: Val::Percent(8.0),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
+        .id();
+    let seed_entity = commands
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "Seed: ",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 30.0,
+                            color: palette.text_dim,
+                        },
+                    ),
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 30.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(20.0),
+                    top: Val::Px(20.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
+        .id();
+    let speed_entity = commands
+        .spawn((
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection::new(
+                        "Speed: ",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 30.0,
+                            color: palette.text_dim,
+                        },
+                    ),
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font: font.0.clone_weak(),
+                            font_size: 30.0,
+                            color: palette.text,
+                        },
+                    ),
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(20.0),
+                    top: Val::Px(55.0),
+                    ..default()
+                },
+                ..default()
+            },
+            Themed(vec![Ink::Dim, Ink::Text]),
+        ))
         .id();
     commands.insert_resource(ScoreUi {
         cur: score_entity,
         high: high_score_entity,
+        time: time_entity,
+        moves: moves_entity,
+        seed: seed_entity,
+        speed: speed_entity,
     });
-    commands.spawn(TextBundle {
-        style: Style {
-            justify_self: JustifySelf::Center,
-            top: Val::Percent(2.0),
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                justify_self: JustifySelf::Center,
+                top: Val::Percent(2.0),
+                ..default()
+            },
+            text: title_text(&title_font, &palette),
             ..default()
         },
-        text: Text::from_section(
-            "2048",
-            TextStyle {
-                font: title_font.0.clone_weak(),
-                font_size: 120.0,
-                color: Color::srgb(0.5, 0.1, 0.4),
+        Themed(vec![Ink::Title]),
+    ));
+}
+
+pub fn title_text(title_font: &TitleFont, palette: &Palette) -> Text {
+    Text::from_section(
+        "2048",
+        TextStyle {
+            font: title_font.0.clone_weak(),
+            font_size: palette.title_font_size,
+            color: palette.accent,
+        },
+    )
+}
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    button: UiButton,
+    font: &PieceFont,
+    palette: &Palette,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Px(65.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                border_color: BorderColor(Color::NONE),
+                background_color: Color::NONE.into(),
+                ..default()
             },
-        ),
-        ..default()
-    });
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font: font.0.clone_weak(),
+                        font_size: 50.0,
+                        color: palette.button,
+                    },
+                ),
+                Themed(vec![Ink::Button]),
+            ));
+        });
 }
-fn new_game_system(
-    mut interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<Button>)>,
+
+fn button_system(
+    mut interaction_query: Query<
+        (&Interaction, &Children, &UiButton),
+        (Changed<Interaction>, With<Button>),
+    >,
     mut text_query: Query<&mut Text>,
     mut new_game_event: EventWriter<NewGameEvent>,
+    mut keep_going_event: EventWriter<KeepGoingEvent>,
+    mut hint_event: EventWriter<HintEvent>,
+    mut settings_event: EventWriter<OpenSettingsEvent>,
+    mut menu_event: EventWriter<OpenMenuEvent>,
+    paused: Res<PausedState>,
+    mut next_state: ResMut<NextState<AppState>>,
+    palette: Res<Palette>,
 ) {
-    for (interaction, children) in &mut interaction_query {
+    for (interaction, children, button) in &mut interaction_query {
         let mut text = text_query.get_mut(children[0]).unwrap();
         match *interaction {
-            Interaction::Pressed => {
-                new_game_event.send(NewGameEvent);
-            }
+            Interaction::Pressed => match button {
+                UiButton::NewGame => {
+                    new_game_event.send(NewGameEvent);
+                }
+                UiButton::KeepGoing => {
+                    keep_going_event.send(KeepGoingEvent);
+                }
+                UiButton::Hint => {
+                    hint_event.send(HintEvent);
+                }
+                UiButton::Settings => {
+                    settings_event.send(OpenSettingsEvent::default());
+                }
+                UiButton::Resume => {
+                    next_state.set(paused.0.clone());
+                }
+                UiButton::Quit => {
+                    menu_event.send(OpenMenuEvent);
+                }
+            },
             Interaction::Hovered => {
-                text.sections[0].style.color = Color::srgb(0.3, 0.3, 0.3);
+                text.sections[0].style.color = palette.text;
             }
             Interaction::None => {
-                text.sections[0].style.color = Color::srgb(0.75, 0.75, 0.75);
+                text.sections[0].style.color = palette.button;
             }
         }
     }
 }
 
-fn create_game_over(mut commands: Commands, font: Res<PieceFont>) {
+fn create_game_over(
+    mut commands: Commands,
+    font: Res<PieceFont>,
+    palette: Res<Palette>,
+    countdown: Option<Res<Countdown>>,
+    puzzle: Option<Res<CurrentPuzzle>>,
+) {
+    let label = match (countdown, puzzle) {
+        (Some(countdown), _) if countdown.is_over() => "TIME UP",
+        (_, Some(_)) => "OUT OF MOVES",
+        _ => "GAME OVER",
+    };
     commands.spawn((
         TextBundle {
             style: Style {
@@ -163,11 +419,11 @@ fn create_game_over(mut commands: Commands, font: Res<PieceFont>) {
                 ..default()
             },
             text: Text::from_section(
-                "GAME OVER",
+                label,
                 TextStyle {
                     font: font.0.clone_weak(),
                     font_size: 200.0,
-                    color: Color::srgb(0.1, 0.1, 0.1),
+                    color: palette.text,
                 },
             ),
             ..default()
@@ -181,17 +437,204 @@ fn remove_game_over(mut commands: Commands, query: Query<Entity, With<GameOverUi
     }
 }
 
+// Puzzles are over once solved, there is no keeping going.
+fn create_win(
+    mut commands: Commands,
+    font: Res<PieceFont>,
+    title_font: Res<TitleFont>,
+    palette: Res<Palette>,
+    puzzle: Option<Res<CurrentPuzzle>>,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(40.0),
+                    ..default()
+                },
+                background_color: palette.background.with_alpha(0.8).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            WinUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                if puzzle.is_some() {
+                    "SOLVED!"
+                } else {
+                    "YOU WIN!"
+                },
+                TextStyle {
+                    font: title_font.0.clone_weak(),
+                    font_size: 100.0,
+                    color: palette.accent,
+                },
+            ));
+            if puzzle.is_some() {
+                spawn_button(parent, "Restart (R)", UiButton::NewGame, &font, &palette);
+                spawn_button(parent, "Levels (M)", UiButton::Quit, &font, &palette);
+            } else {
+                spawn_button(
+                    parent,
+                    "Keep going (K)",
+                    UiButton::KeepGoing,
+                    &font,
+                    &palette,
+                );
+                spawn_button(parent, "New Game (R)", UiButton::NewGame, &font, &palette);
+            }
+        });
+}
+fn remove_win(mut commands: Commands, query: Query<Entity, With<WinUi>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn create_pause(
+    mut commands: Commands,
+    font: Res<PieceFont>,
+    title_font: Res<TitleFont>,
+    palette: Res<Palette>,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(20.0),
+                    ..default()
+                },
+                background_color: palette.background.with_alpha(0.8).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            PauseUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "PAUSED",
+                TextStyle {
+                    font: title_font.0.clone_weak(),
+                    font_size: 100.0,
+                    color: palette.accent,
+                },
+            ));
+            spawn_button(parent, "Resume (Esc)", UiButton::Resume, &font, &palette);
+            spawn_button(parent, "Restart (R)", UiButton::NewGame, &font, &palette);
+            spawn_button(parent, "Settings (O)", UiButton::Settings, &font, &palette);
+            spawn_button(parent, "Quit to menu (M)", UiButton::Quit, &font, &palette);
+        });
+}
+fn remove_pause(mut commands: Commands, query: Query<Entity, With<PauseUi>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 fn update_score_ui(
     score: Res<Score>,
     score_ui: Res<ScoreUi>,
-    high_score: Res<HighScore>,
+    high_scores: Res<HighScores>,
+    mode: Res<GameMode>,
     mut query: Query<&mut Text>,
 ) {
     if let Ok(mut text) = query.get_mut(score_ui.cur) {
         text.sections[1].value = score.0.to_string();
     }
     if let Ok(mut text) = query.get_mut(score_ui.high) {
-        text.sections[1].value = high_score.0.to_string();
+        text.sections[1].value = high_scores.get(*mode).to_string();
+    }
+}
+
+fn update_time_ui(
+    countdown: Option<Res<Countdown>>,
+    score_ui: Res<ScoreUi>,
+    mut query: Query<(&mut Text, &mut Visibility)>,
+) {
+    let Ok((mut text, mut visibility)) = query.get_mut(score_ui.time) else {
+        return;
+    };
+    match countdown {
+        Some(countdown) => {
+            // rounded up, so the clock reads 0:00 only once time is up
+            let secs = countdown.0.as_secs() + u64::from(countdown.0.subsec_nanos() > 0);
+            text.sections[1].value = format!("{}:{:02}", secs / 60, secs % 60);
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+// Daily games show their date instead of the seed, puzzles their name and goal.
+fn update_seed_ui(
+    game: Res<Game>,
+    daily: Option<Res<DailyGame>>,
+    puzzle: Option<Res<CurrentPuzzle>>,
+    score_ui: Res<ScoreUi>,
+    mut query: Query<&mut Text>,
+) {
+    if let Ok(mut text) = query.get_mut(score_ui.seed) {
+        if let Some(puzzle) = puzzle {
+            text.sections[0].value = format!("{}: ", puzzle.puzzle.name);
+            text.sections[1].value = puzzle.puzzle.goal.label();
+            return;
+        }
+        match daily {
+            Some(daily) if daily.practice => {
+                text.sections[0].value = "Daily: ".to_string();
+                text.sections[1].value = format!("{} (practice)", daily.day);
+            }
+            Some(daily) => {
+                text.sections[0].value = "Daily: ".to_string();
+                text.sections[1].value = daily.day.to_string();
+            }
+            None => {
+                text.sections[0].value = "Seed: ".to_string();
+                text.sections[1].value = game.rng.seed.to_string();
+            }
+        }
+    }
+}
+
+fn update_moves_ui(
+    puzzle: Option<Res<CurrentPuzzle>>,
+    record: Res<GameRecord>,
+    score_ui: Res<ScoreUi>,
+    mut query: Query<(&mut Text, &mut Visibility)>,
+) {
+    let Ok((mut text, mut visibility)) = query.get_mut(score_ui.moves) else {
+        return;
+    };
+    match puzzle {
+        Some(puzzle) => {
+            text.sections[1].value =
+                format!("{}/{}", record.recording.moves.len(), puzzle.puzzle.moves);
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+fn update_speed_ui(
+    speed: Res<AnimationSpeed>,
+    score_ui: Res<ScoreUi>,
+    mut query: Query<&mut Text>,
+) {
+    if let Ok(mut text) = query.get_mut(score_ui.speed) {
+        text.sections[1].value = format!("{:?}", *speed);
     }
 }
 
@@ -199,7 +642,27 @@ fn update_score_ui(
 struct ScoreUi {
     cur: Entity,
     high: Entity,
+    time: Entity,
+    moves: Entity,
+    seed: Entity,
+    speed: Entity,
 }
 
 #[derive(Component)]
 struct GameOverUi;
+
+#[derive(Component)]
+struct WinUi;
+
+#[derive(Component)]
+struct PauseUi;
+
+#[derive(Component, Clone, Copy)]
+enum UiButton {
+    NewGame,
+    KeepGoing,
+    Hint,
+    Settings,
+    Resume,
+    Quit,
+}